@@ -0,0 +1,495 @@
+/*
+    File used to keep track of every video in an archive through the index at its root,
+    and to work out where each video's files go.
+*/
+use crate::file::*;
+use crate::log::*;
+use crate::nfo::*;
+use crate::template::*;
+use crate::ytdlp::*;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+};
+
+pub const INDEX_FILENAME: &str = "archive.json";
+pub const HISTORY_FOLDER: &str = "history";
+pub const THUMBNAIL_SIZES: [&str; 5] = ["default", "medium", "high", "standard", "maxres"];
+
+const META_FILENAME: &str = "meta.json";
+
+// the parts of a meta.json needed to add a video to the index
+#[derive(Deserialize)]
+struct UnindexedMetadata {
+    id: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    creator: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ArchiveIndex {
+    pub videos: BTreeMap<String, ArchiveEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub channel: String,
+    // folder of the video relative to the archive root, empty for the root itself
+    pub dir: String,
+    // file name of the video's artifacts, with {artifact} where the artifact name goes
+    pub name: String,
+    // artifact name -> file path relative to the archive root
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+    // artifact name -> size in bytes when it was archived, used to spot truncated files
+    #[serde(default)]
+    pub sizes: BTreeMap<String, u64>,
+    // artifacts that failed verification and need to be downloaded again
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub damaged: Vec<String>,
+    // set once the video can no longer be found on youtube
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unavailable: Option<Unavailability>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnavailableReason {
+    Deleted,
+    Private,
+    Blocked,
+    Unknown,
+}
+
+impl UnavailableReason {
+    pub fn describe(&self) -> &str {
+        match self {
+            UnavailableReason::Deleted => "deleted",
+            UnavailableReason::Private => "private",
+            UnavailableReason::Blocked => "blocked in the api's region",
+            UnavailableReason::Unknown => "unavailable for an unknown reason",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Unavailability {
+    pub reason: UnavailableReason,
+    // dates as YYYY-MM-DD
+    pub first_seen: String,
+    pub last_checked: String,
+}
+
+impl ArchiveIndex {
+    pub fn load(root: &str) -> Result<ArchiveIndex, String> {
+        let filename = format!("{root}/{INDEX_FILENAME}");
+        if !Path::new(&filename).exists() {
+            return Ok(ArchiveIndex::default());
+        }
+
+        let read_result = fs::read_to_string(&filename);
+        if read_result.is_err() {
+            let error = read_result.err().unwrap();
+            return Err(format!(
+                "Couldn't read archive index {filename}! Error: {error}"
+            ));
+        }
+
+        let parse_result = serde_json::from_str(&read_result.unwrap());
+        if parse_result.is_err() {
+            let error = parse_result.err().unwrap();
+            return Err(format!(
+                "Couldn't parse archive index {filename}! Error: {error}"
+            ));
+        }
+
+        Ok(parse_result.unwrap())
+    }
+
+    /// Loads the index along with every archived video that isn't in it, like the ones archived before
+    /// there was an index, which are found by their `meta.json`.
+    pub fn load_all(root: &str) -> Result<ArchiveIndex, String> {
+        let index_result = ArchiveIndex::load(root);
+        if index_result.is_err() {
+            return Err(index_result.err().unwrap());
+        }
+
+        let mut index = index_result.unwrap();
+        index.add_unindexed(root);
+        Ok(index)
+    }
+
+    fn add_unindexed(&mut self, root: &str) {
+        // videos already in the index are skipped without reading their metadata
+        let indexed: HashSet<String> = self
+            .videos
+            .values()
+            .filter_map(|entry| entry.files.get("meta").cloned())
+            .collect();
+        let mut dirs: Vec<String> = vec![String::new()];
+        while let Some(dir) = dirs.pop() {
+            let entries = fs::read_dir(join_path(root, &dir));
+            if entries.is_err() {
+                continue;
+            }
+
+            for entry in entries.unwrap().flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                    if name != HISTORY_FOLDER {
+                        dirs.push(join_path(&dir, &name));
+                    }
+                    continue;
+                }
+
+                // the file template goes around the artifact name, like {title}.{artifact}
+                if indexed.contains(&join_path(&dir, &name)) {
+                    continue;
+                }
+
+                if let Some(prefix) = name.strip_suffix(META_FILENAME) {
+                    self.add_unindexed_video(ArchivePaths {
+                        root: String::from(root),
+                        dir: dir.clone(),
+                        name: format!("{prefix}{ARTIFACT_MARKER}"),
+                    });
+                }
+            }
+        }
+    }
+
+    fn add_unindexed_video(&mut self, paths: ArchivePaths) {
+        let metadata = fs::read_to_string(paths.get_file("meta", "json"))
+            .ok()
+            .and_then(|contents| serde_json::from_str::<UnindexedMetadata>(&contents).ok());
+        let metadata = match metadata {
+            Some(metadata) if !self.videos.contains_key(&metadata.id) => metadata,
+            _ => return,
+        };
+
+        let mut files: BTreeMap<String, String> = BTreeMap::new();
+        let mut sizes: BTreeMap<String, u64> = BTreeMap::new();
+        for (artifact, relative) in paths.get_known_artifacts() {
+            if let Ok(file) = fs::metadata(paths.get_full_path(&relative)) {
+                sizes.insert(artifact.clone(), file.len());
+                files.insert(artifact, relative);
+            }
+        }
+
+        self.videos.insert(
+            metadata.id.clone(),
+            ArchiveEntry {
+                id: metadata.id,
+                title: metadata.title,
+                channel: metadata.creator,
+                dir: paths.dir,
+                name: paths.name,
+                files,
+                sizes,
+                damaged: Vec::new(),
+                unavailable: None,
+            },
+        );
+    }
+
+    pub fn save(&self, root: &str) -> Result<(), String> {
+        let filename = format!("{root}/{INDEX_FILENAME}");
+        let contents = serde_json::to_string_pretty(self).unwrap();
+        let write_result = write_atomic(&filename, contents.as_bytes());
+        if write_result.is_err() {
+            let error = write_result.err().unwrap();
+            return Err(format!(
+                "Couldn't write archive index {filename}! Error: {error}"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Adds or updates the entry for a video, keeping track of whichever of its artifacts exist.
+    /// `written` holds the artifacts written in this run, which are no longer damaged.
+    /// Nothing is recorded if nothing was written, so that videos that failed aren't taken as archived.
+    pub fn record(&mut self, paths: &ArchivePaths, fields: &TemplateFields, written: &[String]) {
+        if written.is_empty() {
+            return;
+        }
+
+        let entry = self
+            .videos
+            .entry(fields.id.clone())
+            .or_insert_with(|| ArchiveEntry {
+                id: fields.id.clone(),
+                title: String::new(),
+                channel: String::new(),
+                dir: paths.dir.clone(),
+                name: paths.name.clone(),
+                files: BTreeMap::new(),
+                sizes: BTreeMap::new(),
+                damaged: Vec::new(),
+                unavailable: None,
+            });
+
+        // fields only hold the id when metadata wasn't requested
+        if !fields.title.is_empty() {
+            entry.title = fields.title.clone();
+            entry.channel = fields.channel.clone();
+        }
+
+        entry.dir = paths.dir.clone();
+        entry.name = paths.name.clone();
+        for (artifact, relative) in paths.get_known_artifacts() {
+            let metadata = fs::metadata(paths.get_full_path(&relative));
+            if metadata.is_err() {
+                continue;
+            }
+
+            if written.contains(&artifact) || !entry.sizes.contains_key(&artifact) {
+                entry
+                    .sizes
+                    .insert(artifact.clone(), metadata.unwrap().len());
+            }
+
+            entry.files.insert(artifact, relative);
+        }
+
+        entry.damaged.retain(|artifact| !written.contains(artifact));
+    }
+
+    /// Records whether an archived video is still on youtube, keeping the date it was first found missing.
+    /// Returns whether that changed since it was last checked.
+    pub fn set_availability(&mut self, id: &str, reason: Option<UnavailableReason>) -> bool {
+        let entry = self.videos.get_mut(id);
+        if entry.is_none() {
+            return false;
+        }
+
+        let entry = entry.unwrap();
+        let today = Utc::now().date_naive().to_string();
+        let previous = entry
+            .unavailable
+            .as_ref()
+            .map(|unavailable| unavailable.reason);
+        entry.unavailable = reason.map(|reason| Unavailability {
+            reason,
+            first_seen: match &entry.unavailable {
+                Some(unavailable) => unavailable.first_seen.clone(),
+                None => today.clone(),
+            },
+            last_checked: today,
+        });
+
+        previous != reason
+    }
+
+    fn find_video_at(&self, dir: &str, name: &str) -> Option<&ArchiveEntry> {
+        self.videos
+            .values()
+            .find(|entry| entry.dir == dir && entry.name == name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchivePaths {
+    pub root: String,
+    pub dir: String,
+    pub name: String,
+}
+
+impl ArchivePaths {
+    pub fn from_entry(root: &str, entry: &ArchiveEntry) -> ArchivePaths {
+        ArchivePaths {
+            root: String::from(root),
+            dir: entry.dir.clone(),
+            name: entry.name.clone(),
+        }
+    }
+
+    pub fn get_dir(&self) -> String {
+        join_path(&self.root, &self.dir)
+    }
+
+    pub fn get_full_path(&self, relative: &str) -> String {
+        join_path(&self.root, relative)
+    }
+
+    /// Gets the file name of an artifact.
+    /// Names without {artifact} have the artifact name added before the extension instead.
+    fn get_file_name(&self, artifact: &str, extension: &str) -> String {
+        if self.name.contains(ARTIFACT_MARKER) {
+            format!(
+                "{}.{extension}",
+                self.name.replace(ARTIFACT_MARKER, artifact)
+            )
+        } else {
+            format!("{}.{artifact}.{extension}", self.name)
+        }
+    }
+
+    /// Gets the path of an artifact relative to the archive root.
+    pub fn get_relative_file(&self, artifact: &str, extension: &str) -> String {
+        join_path(&self.dir, &self.get_file_name(artifact, extension))
+    }
+
+    pub fn get_file(&self, artifact: &str, extension: &str) -> String {
+        self.get_full_path(&self.get_relative_file(artifact, extension))
+    }
+
+    /// Gets the path of the video itself relative to the archive root.
+    /// Names without {artifact} are used for the video as they are, so it can be named exactly as wanted.
+    pub fn get_relative_video_file(&self, artifact: &str, extension: &str) -> String {
+        if self.name.contains(ARTIFACT_MARKER) {
+            self.get_relative_file(artifact, extension)
+        } else {
+            join_path(&self.dir, &format!("{}.{extension}", self.name))
+        }
+    }
+
+    pub fn get_video_file(&self, artifact: &str, extension: &str) -> String {
+        self.get_full_path(&self.get_relative_video_file(artifact, extension))
+    }
+
+    pub fn get_history_dir(&self) -> String {
+        join_path(&self.get_dir(), HISTORY_FOLDER)
+    }
+
+    /// Gets the path of a file in the history folder, like an older version of an artifact,
+    /// which has the time it was replaced added before its extension.
+    pub fn get_history_file(
+        &self,
+        artifact: &str,
+        extension: &str,
+        timestamp: Option<&str>,
+    ) -> String {
+        let extension = match timestamp {
+            Some(timestamp) => format!("{timestamp}.{extension}"),
+            None => String::from(extension),
+        };
+
+        join_path(
+            &self.get_history_dir(),
+            &self.get_file_name(artifact, &extension),
+        )
+    }
+
+    /// Gets every artifact the archiver can write for a video, as pairs of artifact names and relative paths.
+    pub fn get_known_artifacts(&self) -> Vec<(String, String)> {
+        let mut artifacts: Vec<(String, String)> = Vec::new();
+        artifacts.push((String::from("meta"), self.get_relative_file("meta", "json")));
+        for size in THUMBNAIL_SIZES {
+            let artifact = format!("thumb_{size}");
+            let relative = self.get_relative_file(&artifact, "jpg");
+            artifacts.push((artifact, relative));
+        }
+
+        for artifact in ARTWORK_ARTIFACTS {
+            artifacts.push((
+                String::from(artifact),
+                self.get_relative_file(artifact, "jpg"),
+            ));
+        }
+
+        artifacts.push((
+            String::from(NFO_ARTIFACT),
+            self.get_relative_video_file("source_h264", NFO_ARTIFACT),
+        ));
+        artifacts.push((
+            String::from(INFO_JSON_ARTIFACT),
+            self.get_relative_video_file("source_h264", INFO_JSON_EXTENSION),
+        ));
+        artifacts.push((
+            String::from("source_h264"),
+            self.get_relative_video_file("source_h264", "mp4"),
+        ));
+        artifacts
+    }
+}
+
+fn join_path(first: &str, second: &str) -> String {
+    if first.is_empty() {
+        String::from(second)
+    } else if second.is_empty() {
+        String::from(first)
+    } else {
+        format!("{first}/{second}")
+    }
+}
+
+pub struct PathParameters<'a> {
+    pub root: &'a String,
+    pub folder_template: &'a Template,
+    pub file_template: &'a Template,
+    pub fields: &'a TemplateFields,
+}
+
+/// Works out where a video should be archived to.
+/// Videos already in the index keep their paths, so that later changes to titles don't move them around.
+pub fn resolve_paths(params: PathParameters<'_>, index: &ArchiveIndex) -> ArchivePaths {
+    if let Some(entry) = index.videos.get(&params.fields.id) {
+        return ArchivePaths::from_entry(params.root, entry);
+    }
+
+    let dir = params.folder_template.render(params.fields);
+    let mut name = params.file_template.render(params.fields);
+    if let Some(other) = index.find_video_at(&dir, &name) {
+        failure(format!(
+            "Video {} would be archived to the same files as video {}! Adding its id to the file names to keep both.",
+            params.fields.id, other.id
+        ));
+        name = format!("{name} [{}]", sanitize(&params.fields.id));
+    }
+
+    ArchivePaths {
+        root: params.root.clone(),
+        dir,
+        name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_videos_missing_from_the_index() {
+        let root =
+            std::env::temp_dir().join(format!("youtube_archiver_unindexed_{}", std::process::id()));
+        let root = root.to_string_lossy().to_string();
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(format!("{root}/video00001/{HISTORY_FOLDER}")).unwrap();
+        fs::write(
+            format!("{root}/video00001/meta.json"),
+            r#"{"id": "video00001", "title": "Archiving Test", "creator": "Archive Test"}"#,
+        )
+        .unwrap();
+        fs::write(format!("{root}/video00001/thumb_maxres.jpg"), "jpg").unwrap();
+        // older versions of the metadata aren't videos of their own
+        fs::write(
+            format!("{root}/video00001/{HISTORY_FOLDER}/meta.json"),
+            r#"{"id": "video00002"}"#,
+        )
+        .unwrap();
+
+        let index = ArchiveIndex::load_all(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(index.videos.len(), 1);
+        let entry = &index.videos["video00001"];
+        assert_eq!(entry.dir, "video00001");
+        assert_eq!(entry.title, "Archiving Test");
+        assert_eq!(entry.channel, "Archive Test");
+        assert_eq!(
+            entry.files.keys().collect::<Vec<_>>(),
+            vec!["meta", "thumb_maxres"]
+        );
+        assert_eq!(entry.sizes["thumb_maxres"], 3);
+    }
+}
@@ -0,0 +1,1043 @@
+/*
+    File used to archive videos, playlists and channels, which is everything the command line does
+    and what other programs embedding the archiver use. An `Archiver` is set up once with `Archiver::builder()`
+    and can then archive any number of videos, playlists and channels into the same folder.
+*/
+use crate::archive::*;
+use crate::checksum::*;
+use crate::cobalt::*;
+use crate::failed::*;
+use crate::filter::*;
+use crate::http::*;
+use crate::input::*;
+use crate::log::*;
+use crate::nfo::*;
+use crate::plan::*;
+use crate::progress::*;
+use crate::report::*;
+use crate::template::*;
+use crate::youtube::*;
+use crate::ytdlp::*;
+use chrono::Utc;
+use futures::{pin_mut, stream, Stream, StreamExt};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::create_dir_all;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+
+pub const DEFAULT_API: &str = "https://yt.lemnoslife.com";
+pub const DEFAULT_COBALT: &str = "https://api.cobalt.tools/api/json";
+
+/// Sets up an `Archiver`, starting from the same defaults as the command line.
+pub struct ArchiverBuilder {
+    dir: Option<String>,
+    template: Option<String>,
+    file_template: Option<String>,
+    video: bool,
+    metadata: bool,
+    metadata_format: MetadataFormat,
+    nfo: Option<NfoMode>,
+    filters: VideoFilters,
+    api: String,
+    api_key: Option<String>,
+    cobalt: String,
+    cobalt_key: Option<String>,
+    client_settings: ClientSettings,
+    transport: Option<Arc<dyn Transport>>,
+    api_rate_limit: Option<f64>,
+    cobalt_rate_limit: Option<f64>,
+    thumbnail_rate_limit: Option<f64>,
+}
+
+impl Default for ArchiverBuilder {
+    fn default() -> ArchiverBuilder {
+        ArchiverBuilder {
+            dir: None,
+            template: None,
+            file_template: None,
+            video: true,
+            metadata: true,
+            metadata_format: MetadataFormat::Archiver,
+            nfo: None,
+            filters: VideoFilters::default(),
+            api: String::from(DEFAULT_API),
+            api_key: None,
+            cobalt: String::from(DEFAULT_COBALT),
+            cobalt_key: None,
+            client_settings: ClientSettings::default(),
+            transport: None,
+            api_rate_limit: None,
+            cobalt_rate_limit: None,
+            thumbnail_rate_limit: None,
+        }
+    }
+}
+
+impl ArchiverBuilder {
+    /// The folder to archive into, which is the only setting without a default.
+    pub fn dir(mut self, dir: impl Into<String>) -> ArchiverBuilder {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    pub fn template(mut self, template: Option<String>) -> ArchiverBuilder {
+        self.template = template;
+        self
+    }
+
+    pub fn file_template(mut self, file_template: Option<String>) -> ArchiverBuilder {
+        self.file_template = file_template;
+        self
+    }
+
+    pub fn video(mut self, video: bool) -> ArchiverBuilder {
+        self.video = video;
+        self
+    }
+
+    pub fn metadata(mut self, metadata: bool) -> ArchiverBuilder {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn metadata_format(mut self, metadata_format: MetadataFormat) -> ArchiverBuilder {
+        self.metadata_format = metadata_format;
+        self
+    }
+
+    pub fn nfo(mut self, nfo: Option<NfoMode>) -> ArchiverBuilder {
+        self.nfo = nfo;
+        self
+    }
+
+    pub fn filters(mut self, filters: VideoFilters) -> ArchiverBuilder {
+        self.filters = filters;
+        self
+    }
+
+    pub fn api(mut self, api: impl Into<String>, key: Option<String>) -> ArchiverBuilder {
+        self.api = api.into();
+        self.api_key = key;
+        self
+    }
+
+    pub fn cobalt(mut self, cobalt: impl Into<String>, key: Option<String>) -> ArchiverBuilder {
+        self.cobalt = cobalt.into();
+        self.cobalt_key = key;
+        self
+    }
+
+    pub fn client_settings(mut self, client_settings: ClientSettings) -> ArchiverBuilder {
+        self.client_settings = client_settings;
+        self
+    }
+
+    /// Sends every request through a transport other than the network, like one replaying fixtures.
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> ArchiverBuilder {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Limits requests to the api, cobalt and thumbnails to a number of requests per second.
+    /// Limits are kept per host, so they're shared by every archiver in the process.
+    pub fn rate_limits(
+        mut self,
+        api: Option<f64>,
+        cobalt: Option<f64>,
+        thumbnails: Option<f64>,
+    ) -> ArchiverBuilder {
+        self.api_rate_limit = api;
+        self.cobalt_rate_limit = cobalt;
+        self.thumbnail_rate_limit = thumbnails;
+        self
+    }
+
+    /// Checks the settings and sets up the http client shared by every request.
+    pub fn build(self) -> Result<Archiver, String> {
+        if self.dir.is_none() {
+            return Err(String::from("Provide the folder to archive into!"));
+        }
+
+        let client = create_client(&self.client_settings);
+        if client.is_err() {
+            return Err(client.err().unwrap());
+        }
+
+        let mut client = client.unwrap();
+        if let Some(transport) = self.transport {
+            client = client.with_transport(transport);
+        }
+
+        let rate_limits = [
+            (self.api.clone(), self.api_rate_limit),
+            (self.cobalt.clone(), self.cobalt_rate_limit),
+            (
+                format!("https://{THUMBNAIL_HOST}"),
+                self.thumbnail_rate_limit,
+            ),
+        ];
+        for (url, rate) in rate_limits {
+            if let Some(rate) = rate {
+                let limit_result = set_rate_limit(&url, rate);
+                if limit_result.is_err() {
+                    return Err(limit_result.err().unwrap());
+                }
+            }
+        }
+
+        let archiver = Archiver {
+            dir: self.dir.unwrap(),
+            template: self.template,
+            file_template: self.file_template,
+            video: self.video,
+            metadata: self.metadata,
+            metadata_format: self.metadata_format,
+            nfo: self.nfo,
+            filters: self.filters,
+            api: YouTubeApi {
+                url: self.api,
+                key: self.api_key,
+                client: client.clone(),
+            },
+            cobalt: CobaltApi {
+                url: self.cobalt,
+                key: self.cobalt_key,
+                client,
+            },
+        };
+
+        // templates are checked up front, rather than once the first video has been downloaded
+        let templates = archiver.get_templates(true);
+        if templates.is_err() {
+            return Err(templates.err().unwrap());
+        }
+
+        let (folder_template, _) = templates.unwrap();
+        if archiver.nfo == Some(NfoMode::Episode) && !folder_template.starts_with_channel() {
+            warn(String::from(
+                "No tvshow.nfo will be written, since the first folder of the template isn't named after the channel!",
+            ));
+        }
+
+        Ok(archiver)
+    }
+}
+
+/// Archives videos, playlists and channels into a folder.
+#[derive(Debug, Clone)]
+pub struct Archiver {
+    dir: String,
+    template: Option<String>,
+    file_template: Option<String>,
+    video: bool,
+    metadata: bool,
+    metadata_format: MetadataFormat,
+    nfo: Option<NfoMode>,
+    filters: VideoFilters,
+    api: YouTubeApi,
+    cobalt: CobaltApi,
+}
+
+pub struct VideoArchiveRequest {
+    pub url: String,
+    // videos from lists go into folders named after their ids by default, while single videos go straight into the folder
+    pub in_list: bool,
+}
+
+pub struct PlaylistArchiveRequest {
+    pub url: String,
+}
+
+pub struct ChannelArchiveRequest {
+    pub url: String,
+}
+
+/// Any mix of videos, playlists and channels, where videos in more than one of them are only archived once.
+pub struct ListArchiveRequest {
+    pub urls: Vec<String>,
+}
+
+pub struct PlanRequest {
+    pub urls: Vec<String>,
+    pub in_list: bool,
+}
+
+impl Archiver {
+    pub fn builder() -> ArchiverBuilder {
+        ArchiverBuilder::default()
+    }
+
+    pub fn dir(&self) -> &String {
+        &self.dir
+    }
+
+    pub fn youtube_api(&self) -> &YouTubeApi {
+        &self.api
+    }
+
+    pub(crate) fn filters(&self) -> &VideoFilters {
+        &self.filters
+    }
+
+    /// Gets the folder and file templates to use, where single videos are archived
+    /// straight into `dir` and videos from lists into folders named after their ids by default.
+    fn get_templates(&self, in_list: bool) -> Result<(Template, Template), String> {
+        let folder_template = match &self.template {
+            Some(template) => Template::parse_folder(template),
+            None if in_list => Template::parse_folder("{id}"),
+            None => Template::parse_folder(""),
+        };
+        if folder_template.is_err() {
+            return Err(folder_template.err().unwrap());
+        }
+
+        let file_template =
+            Template::parse_file(self.file_template.as_deref().unwrap_or(ARTIFACT_MARKER));
+        if file_template.is_err() {
+            return Err(file_template.err().unwrap());
+        }
+
+        Ok((folder_template.unwrap(), file_template.unwrap()))
+    }
+
+    fn retry_settings(&self, in_list: bool) -> RetrySettings {
+        RetrySettings {
+            in_list,
+            template: self.template.clone(),
+            file_template: self.file_template.clone(),
+            metadata: self.metadata,
+            video: self.video,
+            metadata_format: self.metadata_format.clone(),
+            nfo: self.nfo.clone(),
+            api: self.api.url.clone(),
+            cobalt: self.cobalt.url.clone(),
+        }
+    }
+
+    /// Archives a single video, returning how it went along with everything that went wrong.
+    pub async fn archive_video(&self, params: VideoArchiveRequest) -> VideoResult {
+        let url = &params.url;
+        let started_at = Utc::now();
+        let started = Instant::now();
+        let id_result = get_id_from_url(url);
+        if id_result.is_err() {
+            // there is nothing to retry later, since the url itself is wrong
+            let error = id_result.err().unwrap();
+            failure(error.clone());
+            return VideoResult {
+                id: url.clone(),
+                url: url.clone(),
+                outcome: VideoOutcome::Failed,
+                artifacts: BTreeMap::new(),
+                bytes: 0,
+                errors: vec![error],
+                retries: 0,
+                started_at: started_at.to_rfc3339(),
+                duration_ms: started.elapsed().as_millis(),
+            };
+        }
+
+        let id = id_result.unwrap();
+        let (attempt, video_log) =
+            log_video(&id, self.archive_artifacts(url, &id, params.in_list)).await;
+        success_with(
+            format!("Finished archiving video {id}!"),
+            LogFields {
+                bytes: Some(attempt.bytes),
+                ..LogFields::since(started)
+            },
+        );
+
+        let mut result = VideoResult {
+            id,
+            url: url.clone(),
+            outcome: attempt.outcome,
+            artifacts: attempt.artifacts,
+            bytes: attempt.bytes,
+            errors: video_log.errors,
+            retries: 0,
+            started_at: started_at.to_rfc3339(),
+            duration_ms: started.elapsed().as_millis(),
+        };
+        self.queue_failure(&mut result, params.in_list);
+        result
+    }
+
+    /// Archives a video from a channel or playlist.
+    pub(crate) async fn archive_list_video(&self, url: &str) -> VideoResult {
+        self.archive_video(VideoArchiveRequest {
+            url: String::from(url),
+            in_list: true,
+        })
+        .await
+    }
+
+    /// Keeps the failed queue up to date with how archiving a video went,
+    /// and counts how many times the video had failed before.
+    fn queue_failure(&self, result: &mut VideoResult, in_list: bool) {
+        let queue_result = FailedQueue::load(&self.dir);
+        if queue_result.is_err() {
+            let error = queue_result.err().unwrap();
+            failure(error);
+            return;
+        }
+
+        let mut queue = queue_result.unwrap();
+        if let Some(failed) = queue.videos.get(&result.id) {
+            result.retries = failed.attempts;
+        }
+
+        if !queue.record(result, self.retry_settings(in_list)) {
+            return;
+        }
+
+        let save_result = queue.save(&self.dir);
+        if save_result.is_err() {
+            let error = save_result.err().unwrap();
+            failure(error);
+        }
+    }
+
+    /// Adds every video with damaged files to the failed queue, so that `retry-failed` downloads those files again.
+    pub fn queue_damaged(&self, index: &ArchiveIndex) -> Result<(), String> {
+        let queue_result = FailedQueue::load(&self.dir);
+        if queue_result.is_err() {
+            return Err(queue_result.err().unwrap());
+        }
+
+        let mut queue = queue_result.unwrap();
+        let damaged = index
+            .videos
+            .values()
+            .filter(|entry| !entry.damaged.is_empty());
+        for entry in damaged {
+            queue.record_damaged(entry, self.retry_settings(false));
+        }
+
+        queue.save(&self.dir)
+    }
+
+    async fn archive_artifacts(&self, url: &String, id: &str, in_list: bool) -> ArchiveAttempt {
+        let id = String::from(id);
+        let templates = self.get_templates(in_list);
+        if templates.is_err() {
+            let error = templates.err().unwrap();
+            failure(format!("Couldn't archive video {id}! Error: {error}"));
+            return ArchiveAttempt::empty(VideoOutcome::Failed);
+        }
+
+        let (folder_template, file_template) = templates.unwrap();
+        let index_result = ArchiveIndex::load_all(&self.dir);
+        if index_result.is_err() {
+            let error = index_result.err().unwrap();
+            failure(format!("Couldn't archive video {id}! Error: {error}"));
+            return ArchiveAttempt::empty(VideoOutcome::Failed);
+        }
+
+        let mut index = index_result.unwrap();
+
+        // metadata is needed up front when the templates use anything other than the id
+        let needs_metadata = folder_template.needs_metadata() || file_template.needs_metadata();
+        let mut item: Option<ItemResponse> = None;
+        if self.metadata || needs_metadata {
+            log_stage("metadata");
+            match request_video_metadata(url, &self.api).await {
+                Ok(VideoAvailability::Available(item_response)) => {
+                    if index.set_availability(&id, None) {
+                        success(format!("Video {id} is available on YouTube again!"));
+                    }
+
+                    item = Some(*item_response);
+                }
+                Ok(VideoAvailability::Unavailable(reason)) => {
+                    warn(format!(
+                        "Video {id} is no longer available on YouTube ({})!",
+                        reason.describe()
+                    ));
+
+                    // there is nothing left to download, but archived videos keep track of when they went missing
+                    if index.videos.contains_key(&id) {
+                        index.set_availability(&id, Some(reason));
+                        let save_result = index.save(&self.dir);
+                        if save_result.is_err() {
+                            let error = save_result.err().unwrap();
+                            failure(error);
+                        }
+                    }
+                    return ArchiveAttempt::empty(VideoOutcome::Skipped);
+                }
+                Err(error) => {
+                    failure(error);
+                    if needs_metadata {
+                        failure(format!(
+                            "Couldn't work out where to archive video {id} without its metadata!"
+                        ));
+                        return ArchiveAttempt::empty(VideoOutcome::Failed);
+                    }
+                }
+            }
+        }
+
+        let fields = match &item {
+            Some(item) => item.get_template_fields(),
+            None => TemplateFields::from_id(id.clone()),
+        };
+        let paths = resolve_paths(
+            PathParameters {
+                root: &self.dir,
+                folder_template: &folder_template,
+                file_template: &file_template,
+                fields: &fields,
+            },
+            &index,
+        );
+
+        // create directory for video if it doesn't already exist
+        let create_result = create_dir_all(paths.get_dir());
+        if create_result.is_err() {
+            let error = create_result.err().unwrap();
+            failure(format!(
+                "Couldn't create folder {} for video {id}! Error: {error}",
+                paths.get_dir()
+            ));
+            return ArchiveAttempt::empty(VideoOutcome::Failed);
+        }
+
+        let mut written: Vec<String> = Vec::new();
+        // artifacts that should have been written, to tell which ones failed
+        let mut expected: Vec<String> = Vec::new();
+        if self.metadata {
+            if self.metadata_format.writes_archiver() {
+                expected.push(String::from("meta"));
+            }
+
+            if self.metadata_format.writes_info_json() {
+                expected.push(String::from(INFO_JSON_ARTIFACT));
+            }
+
+            if let Some(item) = &item {
+                for size in THUMBNAIL_SIZES {
+                    if item.snippet.thumbnails.get(size).is_some() {
+                        expected.push(format!("thumb_{size}"));
+                    }
+                }
+
+                if self.nfo.is_some() {
+                    expected.push(String::from(NFO_ARTIFACT));
+                    expected.extend(ARTWORK_ARTIFACTS.map(String::from));
+                }
+
+                written = request_metadata(MetadataParameters {
+                    item,
+                    paths: &paths,
+                    format: &self.metadata_format,
+                    client: &self.api.client,
+                })
+                .await;
+
+                if let Some(mode) = &self.nfo {
+                    log_stage("nfo");
+                    // videos are grouped into shows by the channel folder, when the template has one
+                    let show_dir = if folder_template.starts_with_channel() && !paths.dir.is_empty()
+                    {
+                        paths.dir.split("/").next().map(String::from)
+                    } else {
+                        None
+                    };
+                    written.extend(write_nfo(NfoParameters {
+                        item,
+                        paths: &paths,
+                        mode,
+                        show_dir,
+                    }));
+                }
+            }
+        }
+
+        if self.video {
+            expected.push(String::from("source_h264"));
+            log_stage("video");
+            let downloaded = request_video(VideoParameters {
+                url,
+                api: &self.cobalt,
+                video_codec: String::from("h264"),
+                filename: paths.get_video_file("source_h264", "mp4"),
+            })
+            .await;
+            if downloaded {
+                written.push(String::from("source_h264"));
+            }
+        }
+
+        // anything that went wrong besides artifacts failing
+        let mut problems = 0;
+        log_stage("index");
+        let checksum_result = update_checksums(&paths, &written);
+        if checksum_result.is_err() {
+            let error = checksum_result.err().unwrap();
+            failure(error);
+            problems += 1;
+        }
+
+        index.record(&paths, &fields, &written);
+        let save_result = index.save(&self.dir);
+        if save_result.is_err() {
+            let error = save_result.err().unwrap();
+            failure(error);
+            problems += 1;
+        }
+
+        let bytes: u64 = index
+            .videos
+            .get(&fields.id)
+            .map(|entry| {
+                written
+                    .iter()
+                    .filter_map(|artifact| entry.sizes.get(artifact))
+                    .sum()
+            })
+            .unwrap_or(0);
+        let mut artifacts: BTreeMap<String, ArtifactOutcome> = BTreeMap::new();
+        for artifact in expected {
+            let outcome = if written.contains(&artifact) {
+                ArtifactOutcome::Written
+            } else {
+                problems += 1;
+                ArtifactOutcome::Failed
+            };
+            artifacts.insert(artifact, outcome);
+        }
+
+        let outcome = if problems == 0 {
+            VideoOutcome::Archived
+        } else if written.is_empty() {
+            VideoOutcome::Failed
+        } else {
+            VideoOutcome::Partial
+        };
+        ArchiveAttempt {
+            outcome,
+            artifacts,
+            bytes,
+        }
+    }
+
+    /// Archives every video in a playlist.
+    pub async fn archive_playlist(&self, params: PlaylistArchiveRequest) -> RunSummary {
+        self.archive_list(ListArchiveRequest {
+            urls: vec![params.url],
+        })
+        .await
+    }
+
+    /// Archives every video on a channel, as each page of them is listed.
+    pub async fn archive_channel(&self, params: ChannelArchiveRequest) -> RunSummary {
+        let pages = request_channel(ChannelRequest {
+            url: &params.url,
+            api: self.api.clone(),
+            filters: &self.filters,
+        });
+        pin_mut!(pages);
+
+        // videos are archived as each page comes in, rather than after the whole channel has been listed
+        let mut summary = RunSummary::new();
+        let mut found = 0;
+        while let Some(page) = pages.next().await {
+            if page.is_err() {
+                let error = page.err().unwrap();
+                let error =
+                    format!("Encountered an error while getting channel videos! Error: {error}");
+                failure(error.clone());
+                summary.add_error(error);
+                return summary;
+            }
+
+            let page = page.unwrap();
+            found += page.len();
+            for video in page {
+                request(format!(
+                    "Archiving video {} of {found} found so far",
+                    summary.total() + 1
+                ));
+                summary.add(self.archive_list_video(&video).await);
+            }
+        }
+
+        success(String::from(
+            "Finished downloading all videos from provided channel!",
+        ));
+        summary
+    }
+
+    /// Archives every video of a list of videos, playlists and channels.
+    pub async fn archive_list(&self, params: ListArchiveRequest) -> RunSummary {
+        let urls = params.urls;
+        request(format!("Downloading all videos from list {:?}", &urls));
+        let mut ids: HashSet<String> = HashSet::new();
+        let mut duplicates = 0;
+        let mut summary = RunSummary::new();
+        for url in &urls {
+            let mut pages = self.request_video_pages(url);
+            while let Some(page) = pages.next().await {
+                if page.is_err() {
+                    let error = page.err().unwrap();
+                    let error = format!(
+                        "Encountered an error while getting videos from {url}! Error: {error}"
+                    );
+                    failure(error.clone());
+                    summary.add_error(error);
+                    break;
+                }
+
+                for video in take_unique_videos(page.unwrap(), &mut ids, &mut duplicates) {
+                    request(format!(
+                        "Archiving video {} of {} found so far",
+                        summary.total() + 1,
+                        ids.len()
+                    ));
+                    summary.add(self.archive_list_video(&video).await);
+                }
+            }
+        }
+
+        request(format!(
+            "Found {} unique videos, skipped {duplicates} duplicates.",
+            ids.len()
+        ));
+        success(String::from(
+            "Finished downloading all videos from provided list!",
+        ));
+        summary
+    }
+
+    /// Lists the videos of a channel, playlist or single video one page at a time.
+    pub(crate) fn request_video_pages<'a>(
+        &'a self,
+        url: &'a String,
+    ) -> Pin<Box<dyn Stream<Item = Result<Vec<String>, String>> + Send + 'a>> {
+        match get_input_kind(url) {
+            InputKind::Video => Box::pin(stream::iter(vec![Ok(vec![url.clone()])])),
+            InputKind::Playlist => Box::pin(request_playlist(PlaylistRequest {
+                url,
+                api: self.api.clone(),
+                filters: &self.filters,
+            })),
+            InputKind::Channel => Box::pin(request_channel(ChannelRequest {
+                url,
+                api: self.api.clone(),
+                filters: &self.filters,
+            })),
+        }
+    }
+
+    /// Expands channels and playlists into all of their videos up front, for planning a run.
+    async fn collect_videos(&self, urls: &[String]) -> Vec<String> {
+        let mut videos: Vec<String> = Vec::new();
+        let mut ids: HashSet<String> = HashSet::new();
+        let mut duplicates = 0;
+        for url in urls {
+            let mut pages = self.request_video_pages(url);
+            while let Some(page) = pages.next().await {
+                if page.is_err() {
+                    let error = page.err().unwrap();
+                    failure(format!(
+                        "Encountered an error while getting videos from {url}! Error: {error}"
+                    ));
+                    break;
+                }
+
+                videos.append(&mut take_unique_videos(
+                    page.unwrap(),
+                    &mut ids,
+                    &mut duplicates,
+                ));
+            }
+        }
+
+        request(format!(
+            "Found {} unique videos, skipped {duplicates} duplicates.",
+            videos.len()
+        ));
+        videos
+    }
+
+    /// Works out everything archiving a list would do, without downloading anything.
+    pub async fn plan(&self, params: PlanRequest) -> Result<Plan, String> {
+        let videos = self.collect_videos(&params.urls).await;
+        let index_result = ArchiveIndex::load_all(&self.dir);
+        if index_result.is_err() {
+            let error = index_result.err().unwrap();
+            return Err(format!(
+                "Couldn't check the existing archive! Error: {error}"
+            ));
+        }
+
+        let templates = self.get_templates(params.in_list);
+        if templates.is_err() {
+            return Err(templates.err().unwrap());
+        }
+
+        let (folder_template, file_template) = templates.unwrap();
+        Ok(create_plan(
+            PlanParameters {
+                videos: &videos,
+                root: &self.dir,
+                folder_template: &folder_template,
+                file_template: &file_template,
+                metadata: self.metadata,
+                video: self.video,
+                enumeration_calls: get_requests_sent(),
+            },
+            &index_result.unwrap(),
+        ))
+    }
+
+    /// Archives every video in the failed queue again with the settings it first failed with,
+    /// only asking for the metadata or the video if that's what failed.
+    /// Returns nothing if there weren't any failed videos.
+    pub async fn retry_failed(
+        &self,
+        max_attempts: Option<u32>,
+    ) -> Result<Option<RunSummary>, String> {
+        let queue_result = FailedQueue::load(&self.dir);
+        if queue_result.is_err() {
+            return Err(queue_result.err().unwrap());
+        }
+
+        let queue = queue_result.unwrap();
+        if queue.videos.is_empty() {
+            success(String::from("There are no failed videos to retry!"));
+            return Ok(None);
+        }
+
+        request(format!("Retrying {} failed videos.", queue.videos.len()));
+        let mut summary = RunSummary::new();
+        for (id, failed) in queue.videos {
+            if max_attempts.is_some_and(|max_attempts| failed.attempts >= max_attempts) {
+                request(format!(
+                    "Skipping video {id}, which has already been tried {} times.",
+                    failed.attempts
+                ));
+                continue;
+            }
+
+            let settings = &failed.settings;
+            let mut retry_archiver = self.clone();
+            retry_archiver.template = settings.template.clone();
+            retry_archiver.file_template = settings.file_template.clone();
+            retry_archiver.metadata = failed.needs_metadata();
+            retry_archiver.video = failed.needs_video();
+            retry_archiver.metadata_format = settings.metadata_format.clone();
+            retry_archiver.nfo = settings.nfo.clone();
+            retry_archiver.api.url = settings.api.clone();
+            retry_archiver.cobalt.url = settings.cobalt.clone();
+            request(format!(
+                "Retrying video {id} for the {} time.",
+                describe_attempt(failed.attempts + 1)
+            ));
+            summary.add(
+                retry_archiver
+                    .archive_video(VideoArchiveRequest {
+                        url: failed.url.clone(),
+                        in_list: settings.in_list,
+                    })
+                    .await,
+            );
+        }
+
+        Ok(Some(summary))
+    }
+}
+
+/// Keeps only the videos of a page that haven't been seen yet, so that duplicate videos are only archived once.
+fn take_unique_videos(
+    page: Vec<String>,
+    ids: &mut HashSet<String>,
+    duplicates: &mut usize,
+) -> Vec<String> {
+    let mut videos: Vec<String> = Vec::new();
+    for video in page {
+        // urls without an id are still archived, so that they fail and get reported
+        let id = get_id_from_url(&video).unwrap_or_else(|_| video.clone());
+        if ids.insert(id) {
+            videos.push(video);
+        } else {
+            *duplicates += 1;
+        }
+    }
+
+    videos
+}
+
+fn describe_attempt(attempt: u32) -> String {
+    let suffix = match (attempt % 10, attempt % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{attempt}{suffix}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::ReplayTransport;
+    use std::fs;
+
+    fn test_builder(name: &str) -> ArchiverBuilder {
+        let dir =
+            std::env::temp_dir().join(format!("youtube_archiver_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Archiver::builder()
+            .dir(dir.to_string_lossy())
+            .api("https://api.example.com", None)
+            .cobalt("https://cobalt.example.com/", None)
+            .transport(Arc::new(ReplayTransport {
+                directory: format!("{}/tests/fixtures/archiver", env!("CARGO_MANIFEST_DIR")),
+            }))
+    }
+
+    #[tokio::test]
+    async fn archives_a_video() {
+        let archiver = test_builder("archive").build().unwrap();
+        let result = archiver
+            .archive_video(VideoArchiveRequest {
+                url: String::from("https://youtu.be/video00001"),
+                in_list: false,
+            })
+            .await;
+
+        assert_eq!(
+            result.outcome,
+            VideoOutcome::Archived,
+            "{:?}",
+            result.errors
+        );
+        assert_eq!(
+            result.artifacts.keys().collect::<Vec<_>>(),
+            vec!["meta", "source_h264", "thumb_default", "thumb_maxres"]
+        );
+        assert!(result.errors.is_empty());
+
+        let index = ArchiveIndex::load(archiver.dir()).unwrap();
+        assert_eq!(index.videos["video00001"].title, "Archiving Test");
+        assert_eq!(
+            fs::read_to_string(format!("{}/source_h264.mp4", archiver.dir())).unwrap(),
+            "not really an mp4, but the bytes of one\n"
+        );
+        // nothing failed, so nothing is queued to be retried
+        assert!(FailedQueue::load(archiver.dir()).unwrap().videos.is_empty());
+        fs::remove_dir_all(archiver.dir()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn queues_videos_that_fail() {
+        let archiver = test_builder("failed").metadata(false).build().unwrap();
+        let result = archiver
+            .archive_video(VideoArchiveRequest {
+                url: String::from("https://youtu.be/missing0001"),
+                in_list: true,
+            })
+            .await;
+
+        assert_eq!(result.outcome, VideoOutcome::Failed);
+        let queue = FailedQueue::load(archiver.dir()).unwrap();
+        let failed = &queue.videos["missing0001"];
+        assert_eq!(failed.artifacts, vec![String::from("source_h264")]);
+        assert_eq!(failed.attempts, 1);
+        assert!(failed.settings.in_list);
+        assert_eq!(result.retries, 0);
+        // nothing was written, so the video mustn't look archived
+        let index = ArchiveIndex::load(archiver.dir()).unwrap();
+        assert!(!index.videos.contains_key("missing0001"));
+
+        let result = archiver.retry_failed(None).await.unwrap().unwrap();
+        assert_eq!(result.videos[0].retries, 1);
+        let queue = FailedQueue::load(archiver.dir()).unwrap();
+        assert_eq!(queue.videos["missing0001"].attempts, 2);
+        fs::remove_dir_all(archiver.dir()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn fails_videos_whose_folder_cant_be_created() {
+        let archiver = test_builder("blocked").build().unwrap();
+        // a file where the archive should be means no folder can be created inside it
+        fs::write(archiver.dir(), "").unwrap();
+        let result = archiver
+            .archive_video(VideoArchiveRequest {
+                url: String::from("https://youtu.be/video00001"),
+                in_list: true,
+            })
+            .await;
+        fs::remove_file(archiver.dir()).unwrap();
+
+        assert_eq!(result.outcome, VideoOutcome::Failed);
+        assert!(
+            result.errors[0].starts_with("Couldn't create folder"),
+            "{:?}",
+            result.errors
+        );
+    }
+
+    #[tokio::test]
+    async fn queues_damaged_videos() {
+        let archiver = test_builder("damaged").build().unwrap();
+        fs::create_dir_all(archiver.dir()).unwrap();
+        let mut index = ArchiveIndex::default();
+        for (id, damaged) in [
+            ("video00001", vec![String::from("source_h264")]),
+            ("video00002", Vec::new()),
+        ] {
+            index.videos.insert(
+                String::from(id),
+                ArchiveEntry {
+                    id: String::from(id),
+                    title: String::new(),
+                    channel: String::new(),
+                    dir: String::from(id),
+                    name: String::from("{artifact}"),
+                    files: BTreeMap::new(),
+                    sizes: BTreeMap::new(),
+                    damaged,
+                    unavailable: None,
+                },
+            );
+        }
+
+        archiver.queue_damaged(&index).unwrap();
+        let queue = FailedQueue::load(archiver.dir()).unwrap();
+        fs::remove_dir_all(archiver.dir()).unwrap();
+
+        assert_eq!(queue.videos.len(), 1);
+        let failed = &queue.videos["video00001"];
+        assert_eq!(failed.url, "https://youtu.be/video00001");
+        assert_eq!(failed.artifacts, vec![String::from("source_h264")]);
+        assert!(failed.needs_video());
+        assert!(!failed.needs_metadata());
+    }
+
+    #[test]
+    fn checks_settings_when_building() {
+        assert!(Archiver::builder().build().is_err());
+        assert!(test_builder("settings")
+            .file_template(Some(String::from("videos/{id}")))
+            .build()
+            .is_err());
+        assert!(test_builder("settings")
+            .rate_limits(Some(0.0), None, None)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn describes_attempts() {
+        assert_eq!(describe_attempt(1), "1st");
+        assert_eq!(describe_attempt(2), "2nd");
+        assert_eq!(describe_attempt(3), "3rd");
+        assert_eq!(describe_attempt(11), "11th");
+        assert_eq!(describe_attempt(22), "22nd");
+    }
+}
@@ -0,0 +1,188 @@
+/*
+    File used to keep SHA-256 checksums of every file archived for a video, and to verify them later.
+    Checksums are kept in a `sha256sum` compatible file next to the video's other files.
+*/
+use crate::archive::*;
+use crate::file::*;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{ErrorKind, Read},
+    path::Path,
+};
+
+pub const CHECKSUMS_ARTIFACT: &str = "checksums";
+const CHECKSUMS_EXTENSION: &str = "sha256";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileProblem {
+    Missing,
+    Truncated,
+    Corrupted,
+}
+
+impl FileProblem {
+    pub fn describe(&self) -> &str {
+        match self {
+            FileProblem::Missing => "is missing",
+            FileProblem::Truncated => "is smaller than when it was archived",
+            FileProblem::Corrupted => "doesn't match its checksum",
+        }
+    }
+}
+
+pub fn hash_file(filename: &str) -> Result<String, String> {
+    let open_result = File::open(filename);
+    if open_result.is_err() {
+        let error = open_result.err().unwrap();
+        return Err(format!("Couldn't open file {filename}! Error: {error}"));
+    }
+
+    let mut file = open_result.unwrap();
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let read_result = file.read(&mut buffer);
+        if read_result.is_err() {
+            let error = read_result.err().unwrap();
+            return Err(format!("Couldn't read file {filename}! Error: {error}"));
+        }
+
+        let read = read_result.unwrap();
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// Reads a video's checksums as file names (relative to the video's folder) mapped to hashes.
+fn read_checksums(filename: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut checksums: BTreeMap<String, String> = BTreeMap::new();
+    let read_result = fs::read_to_string(filename);
+    if read_result.is_err() {
+        let error = read_result.err().unwrap();
+        if error.kind() == ErrorKind::NotFound {
+            return Ok(checksums);
+        }
+
+        return Err(format!(
+            "Couldn't read checksums {filename}! Error: {error}"
+        ));
+    }
+
+    for line in read_result.unwrap().lines() {
+        // sha256sum writes "<hash>  <name>", or "<hash> *<name>" in binary mode
+        if let Some((hash, name)) = line.split_once(" ") {
+            let name = name.strip_prefix(['*', ' ']).unwrap_or(name);
+            checksums.insert(String::from(name), String::from(hash));
+        }
+    }
+
+    Ok(checksums)
+}
+
+fn get_file_name(relative: &str) -> String {
+    String::from(relative.rsplit("/").next().unwrap_or(relative))
+}
+
+/// Updates the checksums of a video after archiving it.
+/// Only files written in this run, or that have no checksum yet, are hashed again,
+/// so that a file damaged since it was archived isn't recorded as if it were fine.
+pub fn update_checksums(paths: &ArchivePaths, written: &[String]) -> Result<(), String> {
+    let filename = paths.get_file(CHECKSUMS_ARTIFACT, CHECKSUMS_EXTENSION);
+    let checksums_result = read_checksums(&filename);
+    if checksums_result.is_err() {
+        return Err(checksums_result.err().unwrap());
+    }
+
+    let mut checksums = checksums_result.unwrap();
+    for (artifact, relative) in paths.get_known_artifacts() {
+        let name = get_file_name(&relative);
+        let full_path = paths.get_full_path(&relative);
+        if !Path::new(&full_path).exists()
+            || (checksums.contains_key(&name) && !written.contains(&artifact))
+        {
+            continue;
+        }
+
+        let hash = hash_file(&full_path);
+        if hash.is_err() {
+            return Err(hash.err().unwrap());
+        }
+
+        checksums.insert(name, hash.unwrap());
+    }
+
+    if checksums.is_empty() {
+        return Ok(());
+    }
+
+    let mut contents = String::new();
+    for (name, hash) in checksums {
+        contents += &format!("{hash}  {name}\n");
+    }
+
+    let write_result = write_atomic(&filename, contents.as_bytes());
+    if write_result.is_err() {
+        let error = write_result.err().unwrap();
+        return Err(format!(
+            "Couldn't write checksums {filename}! Error: {error}"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks every file recorded for a video against its size in the index and its checksum,
+/// returning the artifacts that need to be downloaded again.
+pub fn verify_video(
+    root: &str,
+    entry: &ArchiveEntry,
+) -> Result<Vec<(String, FileProblem)>, String> {
+    let paths = ArchivePaths::from_entry(root, entry);
+    let checksums_result = read_checksums(&paths.get_file(CHECKSUMS_ARTIFACT, CHECKSUMS_EXTENSION));
+    if checksums_result.is_err() {
+        return Err(checksums_result.err().unwrap());
+    }
+
+    let checksums = checksums_result.unwrap();
+    let mut problems: Vec<(String, FileProblem)> = Vec::new();
+    for (artifact, relative) in &entry.files {
+        let full_path = paths.get_full_path(relative);
+        let metadata = fs::metadata(&full_path);
+        if metadata.is_err() {
+            problems.push((artifact.clone(), FileProblem::Missing));
+            continue;
+        }
+
+        if let Some(size) = entry.sizes.get(artifact) {
+            if metadata.unwrap().len() < *size {
+                problems.push((artifact.clone(), FileProblem::Truncated));
+                continue;
+            }
+        }
+
+        // files archived before checksums were kept can only be checked for being there
+        if let Some(expected) = checksums.get(&get_file_name(relative)) {
+            let hash = hash_file(&full_path);
+            if hash.is_err() {
+                return Err(hash.err().unwrap());
+            }
+
+            if &hash.unwrap() != expected {
+                problems.push((artifact.clone(), FileProblem::Corrupted));
+            }
+        }
+    }
+
+    Ok(problems)
+}
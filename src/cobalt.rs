@@ -1,8 +1,27 @@
+use crate::file::*;
 use crate::http::*;
 use crate::log::*;
-use reqwest::Client;
+use crate::progress::*;
+use reqwest::Response;
 use serde::{Deserialize, Serialize};
-use std::{fs::File, io::Write};
+use std::{fmt, time::Instant};
+
+#[derive(Clone)]
+pub struct CobaltApi {
+    pub url: String,
+    pub key: Option<String>,
+    pub client: HttpClient,
+}
+
+impl fmt::Debug for CobaltApi {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("CobaltApi")
+            .field("url", &self.url)
+            .field("key", &self.key.as_ref().map(|_| "<hidden>"))
+            .finish_non_exhaustive()
+    }
+}
 
 #[allow(non_snake_case)] // needed for cobalt api
 #[derive(Serialize)]
@@ -32,11 +51,13 @@ pub struct ResponseBody {
 #[derive(Debug)]
 pub struct VideoParameters<'a> {
     pub url: &'a String,
+    pub api: &'a CobaltApi,
     pub video_codec: String,
     pub filename: String,
 }
 
-pub async fn request_video(params: VideoParameters<'_>) {
+/// Downloads a video through cobalt, returning whether it was written successfully.
+pub async fn request_video(params: VideoParameters<'_>) -> bool {
     request(format!(
         "Downloading video with these parameters: {params:?}"
     ));
@@ -45,16 +66,19 @@ pub async fn request_video(params: VideoParameters<'_>) {
     if result.is_err() {
         let error = result.err().unwrap();
         failure(format!("Error while downloading video! Error: {error}"));
-        return;
+        return false;
     }
+
+    true
 }
 
 async fn download_video(params: VideoParameters<'_>) -> Result<(), String> {
-    let client = Client::new();
+    let client = &params.api.client;
     let initial_result = post_json_request::<RequestBody>(
         PostJSONRequest {
-            url: String::from("https://api.cobalt.tools/api/json"),
+            url: params.api.url.clone(),
             accept: Some(String::from("application/json")),
+            authorization: params.api.key.as_ref().map(|key| format!("Api-Key {key}")),
             json: RequestBody {
                 url: params.url.as_str(),
                 vCodec: params.video_codec.as_str(),
@@ -70,25 +94,33 @@ async fn download_video(params: VideoParameters<'_>) -> Result<(), String> {
                 tiktokH265: false,
             },
         },
-        &client,
+        client,
     )
     .await;
 
-    if !initial_result.is_ok() {
-        return Err(String::from("Got an error while posting to https://api.cobalt.tools/api/json! Maybe check your internet connection?"));
+    if initial_result.is_err() {
+        return Err(format!(
+            "Got an error while posting to {}! Maybe check your internet connection?",
+            params.api.url
+        ));
     }
 
     success(String::from("Got response from the cobalt api!"));
 
-    let initial_response = initial_result
-        .unwrap()
-        .json::<ResponseBody>()
-        .await
-        .unwrap();
+    let parse_result = initial_result.unwrap().json::<ResponseBody>().await;
+    if parse_result.is_err() {
+        let error = parse_result.err().unwrap();
+        return Err(format!(
+            "Couldn't parse the cobalt api's response! Error: {}",
+            error.without_url()
+        ));
+    }
+
+    let initial_response = parse_result.unwrap();
     let status = initial_response.status;
     match status.as_str() {
         "error" => {
-            let text = initial_response.text.unwrap();
+            let text = initial_response.text.unwrap_or_default();
             return Err(format!(
                 "Got an error posting to the cobalt api! Message: {text}"
             ));
@@ -96,50 +128,50 @@ async fn download_video(params: VideoParameters<'_>) -> Result<(), String> {
         "rate-limit" => {
             return Err(String::from("Rate-limited from the cobalt api."));
         }
+        "stream" if initial_response.url.is_none() => {
+            return Err(String::from(
+                "Got a stream from the cobalt api without a url.",
+            ));
+        }
         "stream" => {
             success(String::from("Got a valid video stream! Now getting file."));
-            let get_response = get_request(
+            let started = Instant::now();
+            let get_result = get_request(
                 GetRequest {
                     url: initial_response.url.unwrap(),
                     accept: None,
                 },
-                &client,
+                client,
             )
-            .await
-            .unwrap();
-
-            let video_contents_result = get_response.bytes().await;
-            if video_contents_result.is_err() {
-                let error = video_contents_result.err().unwrap();
+            .await;
+            if get_result.is_err() {
+                let error = get_result.err().unwrap();
                 return Err(format!(
-                    "Got an error decoding the cobalt api's video! Error: {error}"
+                    "Got an error requesting the cobalt api's video! Error: {error}"
                 ));
             }
-            let video_contents = video_contents_result.unwrap();
-            let mut output_file = File::create(params.filename.clone()).unwrap();
 
-            let write_result = output_file.write_all(&video_contents);
-            if !write_result.is_ok() {
+            // an error page from cobalt or its tunnel would otherwise be saved as the video
+            let response = get_result.unwrap();
+            if !response.status().is_success() {
                 return Err(format!(
-                    "Couldn't write to file {}. Error: {:?}",
-                    params.filename,
-                    write_result.err().unwrap()
+                    "The cobalt api's video responded with status {}!",
+                    response.status()
                 ));
             }
 
-            let flush_result = output_file.flush();
-            if !flush_result.is_ok() {
-                return Err(format!(
-                    "Couldn't flush file {}. Error: {:?}",
-                    params.filename,
-                    flush_result.err().unwrap()
-                ));
+            let bytes = write_stream(response, &params.filename).await;
+            if bytes.is_err() {
+                return Err(bytes.err().unwrap());
             }
 
-            success(format!(
-                "Wrote to requested file {} successfully!",
-                params.filename
-            ));
+            success_with(
+                format!("Wrote to requested file {} successfully!", params.filename),
+                LogFields {
+                    bytes: Some(bytes.unwrap()),
+                    ..LogFields::since(started)
+                },
+            );
         }
         _ => {
             return Err(format!("No implementation for status {status}."));
@@ -148,3 +180,117 @@ async fn download_video(params: VideoParameters<'_>) -> Result<(), String> {
 
     Ok(())
 }
+
+/// Writes a video to its file as it downloads, rather than holding all of it in memory first,
+/// returning how many bytes were written.
+async fn write_stream(mut response: Response, filename: &str) -> Result<u64, String> {
+    let file = AtomicFile::create(filename);
+    if file.is_err() {
+        return Err(file.err().unwrap());
+    }
+
+    let mut file = file.unwrap();
+    let progress = download_progress(response.content_length(), String::from(filename));
+    let mut written: u64 = 0;
+    loop {
+        let chunk = response.chunk().await;
+        if chunk.is_err() {
+            let error = chunk.err().unwrap();
+            progress.abandon();
+            file.discard();
+            return Err(format!(
+                "Got an error downloading the cobalt api's video! Error: {error}"
+            ));
+        }
+
+        let chunk = match chunk.unwrap() {
+            Some(chunk) => chunk,
+            None => break,
+        };
+        let write_result = file.write(&chunk);
+        if write_result.is_err() {
+            progress.abandon();
+            return Err(write_result.err().unwrap());
+        }
+
+        written += chunk.len() as u64;
+        progress.set_position(written);
+    }
+
+    progress.finish_and_clear();
+    let commit_result = file.commit();
+    if commit_result.is_err() {
+        return Err(commit_result.err().unwrap());
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::replay_client;
+    use std::fs;
+
+    fn test_api() -> CobaltApi {
+        CobaltApi {
+            url: String::from("https://cobalt.example.com/"),
+            key: None,
+            client: replay_client("cobalt"),
+        }
+    }
+
+    fn test_directory(name: &str) -> String {
+        let directory =
+            std::env::temp_dir().join(format!("youtube_archiver_{name}_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory.to_string_lossy().to_string()
+    }
+
+    async fn download(id: &str, filename: &str) -> Result<(), String> {
+        let url = format!("https://youtu.be/{id}");
+        download_video(VideoParameters {
+            url: &url,
+            api: &test_api(),
+            video_codec: String::from("h264"),
+            filename: String::from(filename),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn writes_streamed_videos() {
+        let filename = format!("{}/video00001.mp4", test_directory("stream"));
+        download("video00001", &filename).await.unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&filename).unwrap(),
+            "not really an mp4, but the bytes of one\n"
+        );
+        fs::remove_file(&filename).unwrap();
+    }
+
+    #[tokio::test]
+    async fn fails_on_cobalt_statuses_without_a_video() {
+        let directory = test_directory("statuses");
+        for (id, expected) in [
+            ("error00001", "Message: i couldn't process your request :("),
+            ("limited0001", "Rate-limited"),
+            ("invalid0001", "Couldn't parse the cobalt api's response"),
+            ("nourl00001", "without a url"),
+            ("expired0001", "responded with status 404"),
+        ] {
+            let filename = format!("{directory}/{id}.mp4");
+            let error = download(id, &filename).await.err().unwrap();
+            assert!(error.contains(expected), "{id}: {error}");
+            assert!(!std::path::Path::new(&filename).exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_when_cobalt_cant_be_reached() {
+        let filename = format!("{}/missing.mp4", test_directory("unreachable"));
+        let error = download("missing0001", &filename).await.err().unwrap();
+        assert!(error.starts_with("Got an error while posting"), "{error}");
+    }
+}
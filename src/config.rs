@@ -0,0 +1,194 @@
+/*
+    File used to load config files, which provide default values for any command line argument.
+    Arguments passed on the command line always take priority over the config file.
+*/
+use clap::{builder::Resettable, Command};
+use serde::Deserialize;
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+
+const CONFIG_FILENAME: &str = "youtube_archiver.toml";
+
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    profiles: BTreeMap<String, toml::Table>,
+    #[serde(flatten)]
+    values: toml::Table,
+}
+
+/// Finds the value given to an argument like `--config path` or `--config=path`,
+/// used to find the config file before the rest of the arguments can be parsed.
+pub fn find_argument_value(command_line: &[String], name: &str) -> Option<String> {
+    let mut arguments = command_line.iter();
+    while let Some(argument) = arguments.next() {
+        if argument == "--" {
+            break;
+        }
+
+        if argument == name {
+            return arguments.next().cloned();
+        }
+
+        if let Some(value) = argument.strip_prefix(&format!("{name}=")) {
+            return Some(String::from(value));
+        }
+    }
+
+    None
+}
+
+fn find_config_file(explicit: Option<String>) -> Result<Option<PathBuf>, String> {
+    if let Some(explicit) = explicit {
+        let path = PathBuf::from(&explicit);
+        if !path.is_file() {
+            return Err(format!("Couldn't find the config file {explicit}!"));
+        }
+
+        return Ok(Some(path));
+    }
+
+    let local = PathBuf::from(CONFIG_FILENAME);
+    if local.is_file() {
+        return Ok(Some(local));
+    }
+
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(config_home) if !config_home.is_empty() => PathBuf::from(config_home),
+        _ => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".config"),
+            None => return Ok(None),
+        },
+    };
+
+    let global = config_home.join("youtube_archiver").join("config.toml");
+    if global.is_file() {
+        return Ok(Some(global));
+    }
+
+    Ok(None)
+}
+
+pub struct Config {
+    pub path: PathBuf,
+    pub values: toml::Table,
+}
+
+/// Loads the config file and returns its values, with the values of the requested profile on top.
+/// Nothing is logged, since the config can change how logs are written.
+pub fn load_config(
+    explicit: Option<String>,
+    profile: Option<String>,
+) -> Result<Option<Config>, String> {
+    let path_result = find_config_file(explicit);
+    if path_result.is_err() {
+        return Err(path_result.err().unwrap());
+    }
+
+    let path = match path_result.unwrap() {
+        Some(path) => path,
+        None => {
+            if let Some(profile) = profile {
+                return Err(format!(
+                    "The profile {profile} was requested but no config file could be found!"
+                ));
+            }
+
+            return Ok(None);
+        }
+    };
+
+    let read_result = fs::read_to_string(&path);
+    if read_result.is_err() {
+        let error = read_result.err().unwrap();
+        return Err(format!(
+            "Couldn't read config file {}! Error: {error}",
+            path.display()
+        ));
+    }
+
+    let parse_result = toml::from_str::<ConfigFile>(&read_result.unwrap());
+    if parse_result.is_err() {
+        let error = parse_result.err().unwrap();
+        return Err(format!(
+            "Couldn't parse config file {}! Error: {error}",
+            path.display()
+        ));
+    }
+
+    let mut config = parse_result.unwrap();
+    if let Some(profile) = profile {
+        let profile_values = config.profiles.remove(&profile);
+        if profile_values.is_none() {
+            return Err(format!(
+                "The config file {} has no profile named {profile}!",
+                path.display()
+            ));
+        }
+
+        config.values.extend(profile_values.unwrap());
+    }
+
+    Ok(Some(Config {
+        path,
+        values: config.values,
+    }))
+}
+
+/// Turns every config value into the default value of the matching argument,
+/// so that clap still validates it and anything passed on the command line overrides it.
+pub fn apply_config(mut command: Command, values: &toml::Table) -> Result<Command, String> {
+    for (key, value) in values {
+        let id = key.replace("-", "_");
+        if id == "config" || id == "profile" {
+            return Err(format!("{key} can't be set from inside a config file!"));
+        }
+
+        if !command
+            .get_arguments()
+            .any(|argument| argument.get_id() == id.as_str())
+        {
+            return Err(format!("Unknown config key {key}!"));
+        }
+
+        let mut defaults: Vec<String> = Vec::new();
+        match value {
+            toml::Value::String(string) => defaults.push(string.clone()),
+            toml::Value::Array(array) => {
+                for item in array {
+                    match item {
+                        toml::Value::String(string) => defaults.push(string.clone()),
+                        toml::Value::Table(_) | toml::Value::Array(_) => {
+                            return Err(format!("Config key {key} can't contain tables or arrays!"))
+                        }
+                        other => defaults.push(other.to_string()),
+                    }
+                }
+            }
+            toml::Value::Table(_) => {
+                return Err(format!("Config key {key} can't be a table!"));
+            }
+            other => defaults.push(other.to_string()),
+        }
+
+        // groups that need one of their arguments, like --url or --input-file, are now filled in by the config too
+        let groups: Vec<String> = command
+            .get_groups()
+            .filter(|group| {
+                group.is_required_set() && group.get_args().any(|arg| arg == id.as_str())
+            })
+            .map(|group| group.get_id().to_string())
+            .collect();
+        for group in groups {
+            command = command.mut_group(group, |group| group.required(false));
+        }
+
+        command = command.mut_arg(id, |argument| {
+            argument
+                .default_values(defaults)
+                .required(false)
+                .required_unless_present(Resettable::Reset)
+        });
+    }
+
+    Ok(command)
+}
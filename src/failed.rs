@@ -0,0 +1,175 @@
+/*
+    File used to keep a queue of videos that failed to archive in the archive root,
+    so that only what failed has to be tried again with `retry-failed`.
+*/
+use crate::archive::*;
+use crate::file::*;
+use crate::nfo::*;
+use crate::report::*;
+use crate::ytdlp::*;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+pub const FAILED_FILENAME: &str = "failed.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FailedQueue {
+    pub videos: BTreeMap<String, FailedVideo>,
+}
+
+/// The settings a video was archived with, so that retrying it puts everything in the same place.
+/// Api keys are left out, since the queue sits in the archive alongside everything else.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrySettings {
+    pub in_list: bool,
+    pub template: Option<String>,
+    pub file_template: Option<String>,
+    pub metadata: bool,
+    pub video: bool,
+    pub metadata_format: MetadataFormat,
+    pub nfo: Option<NfoMode>,
+    pub api: String,
+    pub cobalt: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedVideo {
+    pub url: String,
+    // artifacts that failed, where an empty list means the video failed before any artifact was tried
+    pub artifacts: Vec<String>,
+    pub errors: Vec<String>,
+    pub attempts: u32,
+    pub first_failed: String,
+    pub last_attempted: String,
+    pub settings: RetrySettings,
+}
+
+impl FailedQueue {
+    pub fn load(root: &str) -> Result<FailedQueue, String> {
+        let filename = format!("{root}/{FAILED_FILENAME}");
+        if !Path::new(&filename).exists() {
+            return Ok(FailedQueue::default());
+        }
+
+        let read_result = fs::read_to_string(&filename);
+        if read_result.is_err() {
+            let error = read_result.err().unwrap();
+            return Err(format!(
+                "Couldn't read failed queue {filename}! Error: {error}"
+            ));
+        }
+
+        let parse_result = serde_json::from_str(&read_result.unwrap());
+        if parse_result.is_err() {
+            let error = parse_result.err().unwrap();
+            return Err(format!(
+                "Couldn't parse failed queue {filename}! Error: {error}"
+            ));
+        }
+
+        Ok(parse_result.unwrap())
+    }
+
+    pub fn save(&self, root: &str) -> Result<(), String> {
+        let filename = format!("{root}/{FAILED_FILENAME}");
+        let contents = serde_json::to_string_pretty(self).unwrap();
+        let write_result = write_atomic(&filename, contents.as_bytes());
+        if write_result.is_err() {
+            let error = write_result.err().unwrap();
+            return Err(format!(
+                "Couldn't write failed queue {filename}! Error: {error}"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Queues a video that failed, or takes it off the queue once it has worked.
+    /// Returns whether the queue changed.
+    pub fn record(&mut self, result: &VideoResult, settings: RetrySettings) -> bool {
+        if matches!(
+            result.outcome,
+            VideoOutcome::Archived | VideoOutcome::Skipped
+        ) {
+            return self.videos.remove(&result.id).is_some();
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let failed = self
+            .videos
+            .entry(result.id.clone())
+            .or_insert_with(|| FailedVideo {
+                url: result.url.clone(),
+                artifacts: Vec::new(),
+                errors: Vec::new(),
+                attempts: 0,
+                first_failed: now.clone(),
+                last_attempted: now.clone(),
+                // retries only ask for what failed, so the settings of the first attempt are the ones kept
+                settings,
+            });
+
+        failed.artifacts = result
+            .artifacts
+            .iter()
+            .filter(|(_, outcome)| **outcome == ArtifactOutcome::Failed)
+            .map(|(artifact, _)| artifact.clone())
+            .collect();
+        failed.errors = result.errors.clone();
+        failed.attempts += 1;
+        failed.last_attempted = now;
+        true
+    }
+
+    /// Queues the damaged files of an archived video, so that `retry-failed` downloads them again.
+    pub fn record_damaged(&mut self, entry: &ArchiveEntry, settings: RetrySettings) {
+        let now = Utc::now().to_rfc3339();
+        let failed = self
+            .videos
+            .entry(entry.id.clone())
+            .or_insert_with(|| FailedVideo {
+                url: format!("https://youtu.be/{}", entry.id),
+                artifacts: entry.damaged.clone(),
+                errors: Vec::new(),
+                attempts: 0,
+                first_failed: now.clone(),
+                last_attempted: now,
+                settings,
+            });
+
+        for artifact in &entry.damaged {
+            let error = format!("The {artifact} file failed verification!");
+            if !failed.errors.contains(&error) {
+                failed.errors.push(error);
+            }
+
+            // videos that failed before any artifact was tried already have everything downloaded again
+            if !failed.artifacts.is_empty() && !failed.artifacts.contains(artifact) {
+                failed.artifacts.push(artifact.clone());
+            }
+        }
+    }
+}
+
+impl FailedVideo {
+    /// Whether the video itself has to be downloaded again, rather than only its metadata.
+    pub fn needs_video(&self) -> bool {
+        self.settings.video
+            && (self.artifacts.is_empty()
+                || self
+                    .artifacts
+                    .iter()
+                    .any(|artifact| artifact == "source_h264"))
+    }
+
+    /// Whether any of the metadata, thumbnails or NFO files have to be written again.
+    pub fn needs_metadata(&self) -> bool {
+        self.settings.metadata
+            && (self.artifacts.is_empty()
+                || self
+                    .artifacts
+                    .iter()
+                    .any(|artifact| artifact != "source_h264"))
+    }
+}
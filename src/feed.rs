@@ -0,0 +1,183 @@
+/*
+    File used to find the latest videos of channels and playlists through their Atom feeds,
+    which don't use up any api quota, unlike listing every video through the api.
+*/
+use crate::filter::*;
+use crate::http::*;
+use crate::input::*;
+use crate::log::*;
+use crate::youtube::*;
+use quick_xml::{events::Event, Reader};
+use std::collections::HashMap;
+
+const FEED_URL: &str = "https://www.youtube.com/feeds/videos.xml";
+
+pub struct FeedRequest<'a> {
+    pub url: &'a String,
+    pub api: YouTubeApi,
+    pub filters: &'a VideoFilters,
+    // channel ids by handle, so that each channel only has its id requested once
+    pub channel_ids: &'a mut HashMap<String, String>,
+}
+
+async fn get_feed_url(params: &mut FeedRequest<'_>, client: &HttpClient) -> Result<String, String> {
+    if get_input_kind(params.url) == InputKind::Playlist {
+        let playlist_id = get_playlist_id_from_url(params.url);
+        if playlist_id.is_err() {
+            return Err(playlist_id.err().unwrap());
+        }
+
+        let playlist_id = playlist_id.unwrap();
+        return Ok(format!("{FEED_URL}?playlist_id={playlist_id}"));
+    }
+
+    let channel_handle = get_channel_handle_from_url(params.url);
+    if channel_handle.is_err() {
+        return Err(channel_handle.err().unwrap());
+    }
+
+    let channel_handle = channel_handle.unwrap();
+    if let Some(channel_id) = params.channel_ids.get(&channel_handle) {
+        return Ok(format!("{FEED_URL}?channel_id={channel_id}"));
+    }
+
+    let channel_id_result = request_channel_id(&channel_handle, &params.api, client).await;
+    if channel_id_result.is_err() {
+        return Err(channel_id_result.err().unwrap());
+    }
+
+    let channel_id = channel_id_result.unwrap();
+    let feed_url = format!("{FEED_URL}?channel_id={channel_id}");
+    params.channel_ids.insert(channel_handle, channel_id);
+    Ok(feed_url)
+}
+
+/// Reads the video ids out of an Atom feed, in the order they're listed (newest first).
+fn parse_feed(feed: &str) -> Result<Vec<String>, String> {
+    let mut reader = Reader::from_str(feed);
+    let mut ids: Vec<String> = Vec::new();
+    let mut in_video_id = false;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => in_video_id = element.name().as_ref() == b"yt:videoId",
+            Ok(Event::Text(text)) if in_video_id => {
+                let id = text.unescape();
+                if id.is_err() {
+                    let error = id.err().unwrap();
+                    return Err(format!(
+                        "There was an error reading a video id! Error: {error}"
+                    ));
+                }
+
+                ids.push(id.unwrap().trim().to_string());
+            }
+            Ok(Event::End(_)) => in_video_id = false,
+            Ok(Event::Eof) => break,
+            Err(error) => {
+                return Err(format!(
+                    "There was an error parsing the feed at position {}! Error: {error}",
+                    reader.buffer_position()
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Gets the latest videos of a channel or playlist from its feed, returning the urls of the ones that pass the filters.
+/// Feeds only list the last 15 videos, so they can't replace listing every video now and then.
+pub async fn request_feed(mut params: FeedRequest<'_>) -> Result<Vec<String>, String> {
+    if get_input_kind(params.url) == InputKind::Video {
+        return Ok(vec![params.url.clone()]);
+    }
+
+    let client = params.api.client.clone();
+    let feed_url_result = get_feed_url(&mut params, &client).await;
+    if feed_url_result.is_err() {
+        return Err(feed_url_result.err().unwrap());
+    }
+
+    let feed_url = feed_url_result.unwrap();
+    request(format!("Requesting the latest videos from {feed_url}"));
+    let result = get_request(
+        GetRequest {
+            url: feed_url.clone(),
+            accept: Some(String::from("application/atom+xml")),
+        },
+        &client,
+    )
+    .await;
+    if result.is_err() {
+        let error = result.err().unwrap();
+        return Err(format!(
+            "There was an error requesting the feed {feed_url}! Error: {error}"
+        ));
+    }
+
+    let response = result.unwrap();
+    if !response.status().is_success() {
+        return Err(format!(
+            "The feed {feed_url} responded with status {}!",
+            response.status()
+        ));
+    }
+
+    let text_result = response.text().await;
+    if text_result.is_err() {
+        let error = text_result.err().unwrap();
+        return Err(format!(
+            "There was an error reading the feed {feed_url}! Error: {error}"
+        ));
+    }
+
+    let ids = parse_feed(&text_result.unwrap());
+    if ids.is_err() {
+        return Err(ids.err().unwrap());
+    }
+
+    filter_page(
+        ids.unwrap(),
+        PageFilterParameters {
+            api: &params.api,
+            filters: params.filters,
+            client: &client,
+        },
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_video_ids_in_order() {
+        let feed = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns:yt="http://www.youtube.com/xml/schemas/2015" xmlns="http://www.w3.org/2005/Atom">
+  <yt:channelId>UCarchivetest</yt:channelId>
+  <title>Archive Test</title>
+  <entry>
+    <id>yt:video:video00002</id>
+    <yt:videoId>video00002</yt:videoId>
+    <title>Newer</title>
+  </entry>
+  <entry>
+    <id>yt:video:video00001</id>
+    <yt:videoId> video00001 </yt:videoId>
+    <title>Older</title>
+  </entry>
+</feed>"#;
+
+        assert_eq!(
+            parse_feed(feed).unwrap(),
+            vec![String::from("video00002"), String::from("video00001")]
+        );
+    }
+
+    #[test]
+    fn fails_on_broken_feeds() {
+        assert!(parse_feed("<feed><entry></feed>").is_err());
+    }
+}
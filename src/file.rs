@@ -0,0 +1,111 @@
+/*
+    File used to write files atomically, so that a crash or a failed download never leaves
+    a truncated file behind that looks like a complete one.
+*/
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+const TEMPORARY_SUFFIX: &str = ".part";
+
+/// A file that is written under a temporary name in the same folder,
+/// and only renamed to its real name once everything has been written and synced to disk.
+pub struct AtomicFile {
+    filename: String,
+    temporary_filename: String,
+    file: File,
+}
+
+impl AtomicFile {
+    pub fn create(filename: &str) -> Result<AtomicFile, String> {
+        let temporary_filename = format!("{filename}{TEMPORARY_SUFFIX}");
+        let create_result = File::create(&temporary_filename);
+        if create_result.is_err() {
+            let error = create_result.err().unwrap();
+            return Err(format!(
+                "Couldn't create file {temporary_filename}! Error: {error}"
+            ));
+        }
+
+        Ok(AtomicFile {
+            filename: String::from(filename),
+            temporary_filename,
+            file: create_result.unwrap(),
+        })
+    }
+
+    pub fn write(&mut self, contents: &[u8]) -> Result<(), String> {
+        let write_result = self.file.write_all(contents);
+        if write_result.is_err() {
+            let error = write_result.err().unwrap();
+            self.discard();
+            return Err(format!(
+                "Couldn't write to file {}. Error: {error}",
+                self.temporary_filename
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Syncs the file to disk and moves it to its real name, replacing any file already there.
+    pub fn commit(self) -> Result<(), String> {
+        let sync_result = self.file.sync_all();
+        if sync_result.is_err() {
+            let error = sync_result.err().unwrap();
+            self.discard();
+            return Err(format!(
+                "Couldn't sync file {} to disk. Error: {error}",
+                self.temporary_filename
+            ));
+        }
+
+        let rename_result = fs::rename(&self.temporary_filename, &self.filename);
+        if rename_result.is_err() {
+            let error = rename_result.err().unwrap();
+            self.discard();
+            return Err(format!(
+                "Couldn't move file {} to {}. Error: {error}",
+                self.temporary_filename, self.filename
+            ));
+        }
+
+        // the rename itself only survives a crash once the folder has been synced too,
+        // which isn't possible on every platform so failing to do it is ignored
+        if let Some(parent) = Path::new(&self.filename).parent() {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            if let Ok(folder) = File::open(parent) {
+                let _ = folder.sync_all();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the temporary file, for when the contents can't be finished.
+    pub fn discard(&self) {
+        let _ = fs::remove_file(&self.temporary_filename);
+    }
+}
+
+/// Writes a whole file at once through an `AtomicFile`.
+pub fn write_atomic(filename: &str, contents: &[u8]) -> Result<(), String> {
+    let file = AtomicFile::create(filename);
+    if file.is_err() {
+        return Err(file.err().unwrap());
+    }
+
+    let mut file = file.unwrap();
+    let write_result = file.write(contents);
+    if write_result.is_err() {
+        return Err(write_result.err().unwrap());
+    }
+
+    file.commit()
+}
@@ -0,0 +1,172 @@
+/*
+    File used to filter the videos of channels and playlists before anything is downloaded.
+*/
+use crate::youtube::*;
+use chrono::{DateTime, NaiveDate};
+use clap::ValueEnum;
+use regex::Regex;
+
+#[derive(Debug, Clone, Default, PartialEq, ValueEnum)]
+pub enum ShortsFilter {
+    #[default]
+    Include,
+    Exclude,
+    Only,
+}
+
+#[derive(Debug, Clone)]
+pub struct VideoFilters {
+    pub streams: bool,
+    pub premieres: bool,
+    pub upcoming: bool,
+    pub after: Option<NaiveDate>,
+    pub before: Option<NaiveDate>,
+    pub min_duration: Option<u64>,
+    pub max_duration: Option<u64>,
+    pub title_match: Option<Regex>,
+    pub title_reject: Option<Regex>,
+    pub description_match: Option<Regex>,
+    pub description_reject: Option<Regex>,
+    pub shorts: ShortsFilter,
+    pub min_views: Option<u64>,
+}
+
+// every video passes, like when no filters are given on the command line
+impl Default for VideoFilters {
+    fn default() -> VideoFilters {
+        VideoFilters {
+            streams: true,
+            premieres: true,
+            upcoming: true,
+            after: None,
+            before: None,
+            min_duration: None,
+            max_duration: None,
+            title_match: None,
+            title_reject: None,
+            description_match: None,
+            description_reject: None,
+            shorts: ShortsFilter::Include,
+            min_views: None,
+        }
+    }
+}
+
+impl VideoFilters {
+    /// Whether any filter needs the details of each video, which take an extra request per 50 videos.
+    pub fn is_active(&self) -> bool {
+        !self.streams
+            || !self.premieres
+            || !self.upcoming
+            || self.after.is_some()
+            || self.before.is_some()
+            || self.min_duration.is_some()
+            || self.max_duration.is_some()
+            || self.title_match.is_some()
+            || self.title_reject.is_some()
+            || self.description_match.is_some()
+            || self.description_reject.is_some()
+            || self.shorts != ShortsFilter::Include
+            || self.min_views.is_some()
+    }
+
+    /// Checks a video against every filter apart from the shorts filter, returning why it was filtered out if it was.
+    /// Whether a video is a short takes a request of its own, so it's checked with `check_short` instead.
+    pub fn check(&self, details: &VideoDetailsResponse) -> Result<(), String> {
+        match details.get_broadcast_kind() {
+            BroadcastKind::Stream if !self.streams => {
+                return Err(String::from("it is a stream"));
+            }
+            BroadcastKind::Premiere if !self.premieres => {
+                return Err(String::from("it is a premiere"));
+            }
+            BroadcastKind::Upcoming if !self.upcoming => {
+                return Err(String::from("it is an upcoming stream or premiere"));
+            }
+            _ => {}
+        }
+
+        let published = DateTime::parse_from_rfc3339(&details.snippet.publishedAt)
+            .map(|date| date.date_naive())
+            .ok();
+        if let (Some(after), Some(published)) = (self.after, published) {
+            if published < after {
+                return Err(format!("it was published before {after}"));
+            }
+        }
+
+        if let (Some(before), Some(published)) = (self.before, published) {
+            if published > before {
+                return Err(format!("it was published after {before}"));
+            }
+        }
+
+        let duration = parse_duration(&details.contentDetails.duration);
+        if let Some(min_duration) = self.min_duration {
+            if duration < min_duration {
+                return Err(format!("it is shorter than {min_duration} seconds"));
+            }
+        }
+
+        if let Some(max_duration) = self.max_duration {
+            if duration > max_duration {
+                return Err(format!("it is longer than {max_duration} seconds"));
+            }
+        }
+
+        let title = &details.snippet.title;
+        if let Some(title_match) = &self.title_match {
+            if !title_match.is_match(title) {
+                return Err(format!("its title doesn't match {title_match}"));
+            }
+        }
+
+        if let Some(title_reject) = &self.title_reject {
+            if title_reject.is_match(title) {
+                return Err(format!("its title matches {title_reject}"));
+            }
+        }
+
+        let description = &details.snippet.description;
+        if let Some(description_match) = &self.description_match {
+            if !description_match.is_match(description) {
+                return Err(format!("its description doesn't match {description_match}"));
+            }
+        }
+
+        if let Some(description_reject) = &self.description_reject {
+            if description_reject.is_match(description) {
+                return Err(format!("its description matches {description_reject}"));
+            }
+        }
+
+        if let Some(min_views) = self.min_views {
+            // view counts can be hidden, in which case the video can't be shown to have enough
+            let views = details
+                .statistics
+                .as_ref()
+                .and_then(|statistics| statistics.viewCount.as_ref())
+                .and_then(|views| views.parse::<u64>().ok())
+                .unwrap_or(0);
+            if views < min_views {
+                return Err(format!("it has fewer than {min_views} views"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the shorts filter needs to know if each video is a short.
+    pub fn checks_shorts(&self) -> bool {
+        self.shorts != ShortsFilter::Include
+    }
+
+    /// Checks whether a video is a short against the shorts filter, returning why it was filtered out if it was.
+    pub fn check_short(&self, is_short: bool) -> Result<(), String> {
+        match self.shorts {
+            ShortsFilter::Exclude if is_short => Err(String::from("it is a short")),
+            ShortsFilter::Only if !is_short => Err(String::from("it isn't a short")),
+            _ => Ok(()),
+        }
+    }
+}
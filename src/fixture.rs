@@ -0,0 +1,314 @@
+/*
+    File used to record http responses to fixture files and replay them, so that the archiver can be run and tested
+    without network access. Each fixture is a `.json` file describing the request and response, and a `.body` file
+    next to it with the response's raw bytes.
+*/
+use crate::file::*;
+use crate::http::*;
+use axum::http::Response as HttpResponse;
+use futures::future::BoxFuture;
+use reqwest::{Client, Request, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, create_dir_all};
+use std::path::Path;
+
+const FIXTURE_EXTENSION: &str = "json";
+const BODY_EXTENSION: &str = "body";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Fixture {
+    method: String,
+    url: String,
+    // fixtures without a request body match any request to the url
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_body: Option<String>,
+    status: u16,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+impl Fixture {
+    fn matches(&self, method: &str, url: &str, request_body: &Option<String>) -> bool {
+        self.method == method
+            && self.url == url
+            && (self.request_body.is_none() || self.request_body == *request_body)
+    }
+}
+
+fn get_request_body(request: &Request) -> Option<String> {
+    request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map(|bytes| String::from_utf8_lossy(bytes).to_string())
+}
+
+/// Names a fixture after its url so they're easy to find, with a hash so that requests to the same url don't clash.
+fn get_fixture_name(method: &str, url: &str, request_body: &Option<String>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method);
+    hasher.update(url);
+    hasher.update(request_body.clone().unwrap_or_default());
+    let hash = format!("{:x}", hasher.finalize());
+
+    let without_scheme = url.split("://").last().unwrap_or(url);
+    let path = without_scheme.split('?').next().unwrap_or(without_scheme);
+    let slug: String = path
+        .chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() {
+                character
+            } else {
+                '_'
+            }
+        })
+        .take(60)
+        .collect();
+    format!("{}_{slug}_{}", method.to_lowercase(), &hash[..16])
+}
+
+fn create_response(fixture: &Fixture, body: Vec<u8>) -> Result<Response, String> {
+    let mut builder = HttpResponse::builder().status(fixture.status);
+    for (name, value) in &fixture.headers {
+        builder = builder.header(name, value);
+    }
+
+    let response = builder.body(body);
+    if response.is_err() {
+        let error = response.err().unwrap();
+        return Err(format!(
+            "Couldn't create response for fixture of {}! Error: {error}",
+            fixture.url
+        ));
+    }
+
+    Ok(Response::from(response.unwrap()))
+}
+
+/// Sends requests over the network and writes every response to a fixture in `directory`.
+pub struct RecordingTransport {
+    pub directory: String,
+}
+
+impl RecordingTransport {
+    async fn record(&self, client: &Client, request: Request) -> Result<Response, String> {
+        let method = request.method().to_string();
+        // api keys are left out, since fixtures are meant to be committed
+        let url = redact_url(request.url().as_str());
+        let request_body = get_request_body(&request);
+
+        let response = LiveTransport.send(client, request).await?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| {
+                matches!(
+                    name.as_str(),
+                    "content-type" | "content-length" | "location" | "retry-after"
+                )
+            })
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).to_string(),
+                )
+            })
+            .collect();
+        let body_result = response.bytes().await;
+        if body_result.is_err() {
+            let error = body_result.err().unwrap();
+            return Err(error.without_url().to_string());
+        }
+        let body = body_result.unwrap().to_vec();
+
+        let name = get_fixture_name(&method, &url, &request_body);
+        let fixture = Fixture {
+            method,
+            url,
+            request_body,
+            status,
+            headers,
+        };
+        let create_result = create_dir_all(&self.directory);
+        if create_result.is_err() {
+            let error = create_result.err().unwrap();
+            return Err(format!(
+                "Couldn't create fixture directory {}! Error: {error}",
+                self.directory
+            ));
+        }
+        let fixture_filename = format!("{}/{name}.{FIXTURE_EXTENSION}", self.directory);
+        let body_filename = format!("{}/{name}.{BODY_EXTENSION}", self.directory);
+        // the body goes first, so an interrupted recording never leaves a fixture pointing at a missing or partial body
+        let write_result = write_atomic(&body_filename, &body).and_then(|_| {
+            write_atomic(
+                &fixture_filename,
+                serde_json::to_string_pretty(&fixture).unwrap().as_bytes(),
+            )
+        });
+        if write_result.is_err() {
+            let error = write_result.err().unwrap();
+            return Err(format!(
+                "Couldn't write fixture {fixture_filename}! Error: {error}"
+            ));
+        }
+
+        create_response(&fixture, body)
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn send<'a>(
+        &'a self,
+        client: &'a Client,
+        request: Request,
+    ) -> BoxFuture<'a, Result<Response, String>> {
+        Box::pin(self.record(client, request))
+    }
+}
+
+/// Answers requests from the fixtures in `directory` without touching the network.
+/// Requests without a fixture fail, so that a test can't quietly reach out to the real apis.
+pub struct ReplayTransport {
+    pub directory: String,
+}
+
+impl ReplayTransport {
+    fn find_fixture(&self, request: &Request) -> Result<(Fixture, Vec<u8>), String> {
+        let method = request.method().to_string();
+        let url = redact_url(request.url().as_str());
+        let request_body = get_request_body(request);
+
+        let entries = fs::read_dir(&self.directory);
+        if entries.is_err() {
+            let error = entries.err().unwrap();
+            return Err(format!(
+                "Couldn't read fixture directory {}! Error: {error}",
+                self.directory
+            ));
+        }
+
+        let mut filenames: Vec<_> = entries
+            .unwrap()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == FIXTURE_EXTENSION)
+            })
+            .collect();
+        filenames.sort();
+
+        for filename in filenames {
+            let fixture = fs::read_to_string(&filename)
+                .ok()
+                .and_then(|contents| serde_json::from_str::<Fixture>(&contents).ok());
+            if fixture
+                .as_ref()
+                .is_some_and(|fixture| fixture.matches(&method, &url, &request_body))
+            {
+                let body_filename = filename.with_extension(BODY_EXTENSION);
+                // a fixture without a body file has an empty body
+                let body = if Path::new(&body_filename).exists() {
+                    fs::read(&body_filename).unwrap_or_default()
+                } else {
+                    Vec::new()
+                };
+                return Ok((fixture.unwrap(), body));
+            }
+        }
+
+        Err(format!(
+            "No fixture in {} for {method} {url}!",
+            self.directory
+        ))
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send<'a>(
+        &'a self,
+        _client: &'a Client,
+        request: Request,
+    ) -> BoxFuture<'a, Result<Response, String>> {
+        Box::pin(async move {
+            let (fixture, body) = self.find_fixture(&request)?;
+            create_response(&fixture, body)
+        })
+    }
+}
+
+/// Creates a client that replays the fixtures in `tests/fixtures/<name>`.
+#[cfg(test)]
+pub fn replay_client(name: &str) -> HttpClient {
+    let client = create_client(&ClientSettings::default()).unwrap();
+    client.with_transport(std::sync::Arc::new(ReplayTransport {
+        directory: format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR")),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn replays_a_recorded_response() {
+        let response = get_request(
+            GetRequest {
+                url: String::from(
+                    "https://api.example.com/noKey/channels?part=id&forHandle=@archivetest",
+                ),
+                accept: None,
+            },
+            &replay_client("youtube"),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(
+            response.headers()["content-type"].to_str().unwrap(),
+            "application/json"
+        );
+        assert!(response.text().await.unwrap().contains("UCarchivetest"));
+    }
+
+    #[tokio::test]
+    async fn fails_requests_without_a_fixture() {
+        let result = get_request(
+            GetRequest {
+                url: String::from(
+                    "https://api.example.com/noKey/channels?part=id&forHandle=@missing",
+                ),
+                accept: None,
+            },
+            &replay_client("youtube"),
+        )
+        .await;
+
+        let error = result.err().unwrap();
+        assert!(error.starts_with("No fixture in"), "{error}");
+    }
+
+    #[test]
+    fn names_fixtures_after_their_url() {
+        let name = get_fixture_name(
+            "GET",
+            "https://api.example.com/noKey/videos?part=id&id=abc",
+            &None,
+        );
+        assert!(
+            name.starts_with("get_api_example_com_noKey_videos_"),
+            "{name}"
+        );
+        assert_ne!(
+            name,
+            get_fixture_name(
+                "GET",
+                "https://api.example.com/noKey/videos?part=id&id=def",
+                &None
+            )
+        );
+    }
+}
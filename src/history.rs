@@ -0,0 +1,105 @@
+/*
+    File used to keep older versions of a video's metadata and thumbnails whenever they change,
+    along with a readable log of what changed, so edits made after upload aren't lost.
+*/
+use crate::archive::*;
+use crate::file::*;
+use crate::youtube::*;
+use chrono::Utc;
+use std::fs::{self, create_dir_all};
+
+// no colons, so that snapshots can be named after their timestamp on every filesystem
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const CHANGE_LOG_ARTIFACT: &str = "changes";
+
+pub fn get_timestamp() -> String {
+    Utc::now().format(TIMESTAMP_FORMAT).to_string()
+}
+
+/// Lists how a video's metadata changed, as lines for the change log.
+pub fn describe_changes(old: &ArchivedMetadata, new: &ArchivedMetadata) -> Vec<String> {
+    let mut changes: Vec<String> = Vec::new();
+    if old.title != new.title {
+        changes.push(format!(
+            "title changed from {:?} to {:?}",
+            old.title, new.title
+        ));
+    }
+
+    if old.description != new.description {
+        changes.push(String::from("description changed"));
+    }
+
+    if old.creator != new.creator {
+        changes.push(format!(
+            "channel name changed from {:?} to {:?}",
+            old.creator, new.creator
+        ));
+    }
+
+    if old.publish_date != new.publish_date {
+        changes.push(format!(
+            "publish date changed from {} to {}",
+            old.publish_date, new.publish_date
+        ));
+    }
+
+    let added: Vec<&String> = new
+        .tags
+        .iter()
+        .filter(|tag| !old.tags.contains(tag))
+        .collect();
+    if !added.is_empty() {
+        changes.push(format!("tags added: {added:?}"));
+    }
+
+    let removed: Vec<&String> = old
+        .tags
+        .iter()
+        .filter(|tag| !new.tags.contains(tag))
+        .collect();
+    if !removed.is_empty() {
+        changes.push(format!("tags removed: {removed:?}"));
+    }
+
+    if changes.is_empty() && old != new {
+        changes.push(String::from("other metadata changed"));
+    }
+
+    changes
+}
+
+/// Writes a file into the video's history folder, such as the old contents of an artifact before it gets replaced.
+pub fn write_history_file(
+    paths: &ArchivePaths,
+    filename: &str,
+    contents: &[u8],
+) -> Result<(), String> {
+    let create_result = create_dir_all(paths.get_history_dir());
+    if create_result.is_err() {
+        let error = create_result.err().unwrap();
+        return Err(format!(
+            "Couldn't create history folder {}! Error: {error}",
+            paths.get_history_dir()
+        ));
+    }
+
+    write_atomic(filename, contents)
+}
+
+/// Adds an entry to the video's change log, which is kept in its history folder.
+pub fn log_changes(
+    paths: &ArchivePaths,
+    timestamp: &str,
+    changes: &[String],
+) -> Result<(), String> {
+    let filename = paths.get_history_file(CHANGE_LOG_ARTIFACT, "log", None);
+    let mut contents = fs::read_to_string(&filename).unwrap_or_default();
+    contents += &format!("[{timestamp}]\n");
+    for change in changes {
+        contents += &format!("- {change}\n");
+    }
+
+    contents += "\n";
+    write_history_file(paths, &filename, contents.as_bytes())
+}
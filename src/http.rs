@@ -1,40 +1,403 @@
 /*
     File used internally to abstract HTTP requests just that bit more, and to reduce redundancy.
 */
-use reqwest::{Client, Error, Response};
+use crate::log::*;
+use futures::future::BoxFuture;
+use regex::Regex;
+use reqwest::{
+    redirect::Policy, Certificate, Client, Proxy, Request, RequestBuilder, Response, Url,
+};
 use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+static REQUESTS_SENT: AtomicUsize = AtomicUsize::new(0);
+
+/// Gets how many requests have been sent so far, used to estimate api usage.
+pub(crate) fn get_requests_sent() -> usize {
+    REQUESTS_SENT.load(Ordering::Relaxed)
+}
+
+/// Youtube serves thumbnails from its own CDN, apart from the api.
+pub const THUMBNAIL_HOST: &str = "i.ytimg.com";
+
+/// Lets requests to a host through at a steady rate, with room for a short burst after a quiet spell.
+struct TokenBucket {
+    // requests per second, which is also how many can be sent at once
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Takes a token if there is one, or returns how long to wait for the next one.
+    fn take(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        let capacity = self.rate.max(1.0);
+        self.tokens = (self.tokens + now.duration_since(self.updated).as_secs_f64() * self.rate)
+            .min(capacity);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return None;
+        }
+
+        Some(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+    }
+}
+
+// shared by every request no matter where it comes from, so concurrent work shares the same budget
+static RATE_LIMITS: Mutex<BTreeMap<String, TokenBucket>> = Mutex::new(BTreeMap::new());
+
+fn get_host(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(String::from))
+}
+
+/// Limits requests to the host of a url to a number of requests per second.
+pub fn set_rate_limit(url: &str, rate: f64) -> Result<(), String> {
+    if rate.is_nan() || rate <= 0.0 {
+        return Err(format!(
+            "Rate limits have to be more than 0 requests per second, not {rate}!"
+        ));
+    }
+
+    let host = get_host(url).unwrap_or_else(|| String::from(url));
+    RATE_LIMITS.lock().unwrap().insert(
+        host,
+        TokenBucket {
+            rate,
+            tokens: rate.max(1.0),
+            updated: Instant::now(),
+        },
+    );
+    Ok(())
+}
+
+async fn wait_for_rate_limit(url: &str) {
+    let host = match get_host(url) {
+        Some(host) => host,
+        None => return,
+    };
+
+    loop {
+        let wait = match RATE_LIMITS.lock().unwrap().get_mut(&host) {
+            Some(bucket) => bucket.take(),
+            None => None,
+        };
+        match wait {
+            Some(wait) => {
+                trace(
+                    format!(
+                        "Waiting {}ms to stay under the rate limit of {host}",
+                        wait.as_millis()
+                    ),
+                    LogFields::default(),
+                );
+                sleep(wait).await;
+            }
+            None => return,
+        }
+    }
+}
+
+/// Sends requests for the client, which can be swapped out to record responses or replay them without a network.
+pub trait Transport: Send + Sync {
+    fn send<'a>(
+        &'a self,
+        client: &'a Client,
+        request: Request,
+    ) -> BoxFuture<'a, Result<Response, String>>;
+}
+
+/// Sends requests over the network.
+pub struct LiveTransport;
+
+impl Transport for LiveTransport {
+    fn send<'a>(
+        &'a self,
+        client: &'a Client,
+        request: Request,
+    ) -> BoxFuture<'a, Result<Response, String>> {
+        Box::pin(async move {
+            // errors hold the whole url, which would show the api key wherever they're logged
+            let result = client.execute(request).await;
+            if result.is_err() {
+                let error = result.err().unwrap();
+                return Err(error.without_url().to_string());
+            }
+
+            Ok(result.unwrap())
+        })
+    }
+}
+
+/// The client shared by every request, along with whatever sends its requests.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client,
+    // the same client apart from not following redirects, for requests where the redirect is the answer
+    no_redirect_client: Client,
+    transport: Arc<dyn Transport>,
+}
+
+impl std::fmt::Debug for HttpClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpClient").finish_non_exhaustive()
+    }
+}
+
+impl HttpClient {
+    pub fn new(
+        client: Client,
+        no_redirect_client: Client,
+        transport: Arc<dyn Transport>,
+    ) -> HttpClient {
+        HttpClient {
+            client,
+            no_redirect_client,
+            transport,
+        }
+    }
+
+    pub fn with_transport(self, transport: Arc<dyn Transport>) -> HttpClient {
+        HttpClient {
+            client: self.client,
+            no_redirect_client: self.no_redirect_client,
+            transport,
+        }
+    }
+}
+
+pub struct ClientSettings {
+    pub connect_timeout: u64,
+    // longest wait for any one read, so stalled downloads fail without limiting how long a whole download can take
+    pub read_timeout: u64,
+    pub user_agent: String,
+    pub proxy: Option<String>,
+    // PEM files of extra certificates to trust, like the one of a proxy that inspects traffic
+    pub ca_certificates: Vec<String>,
+}
+
+impl Default for ClientSettings {
+    fn default() -> ClientSettings {
+        ClientSettings {
+            connect_timeout: 10,
+            read_timeout: 60,
+            user_agent: get_default_user_agent(),
+            proxy: None,
+            ca_certificates: Vec::new(),
+        }
+    }
+}
+
+pub fn get_default_user_agent() -> String {
+    format!("youtube_archiver/{}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Creates the client shared by every request, so that connections are reused between them.
+/// Proxies from the standard `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` variables are used
+/// unless one is given in the settings.
+pub fn create_client(settings: &ClientSettings) -> Result<HttpClient, String> {
+    let client = build_client(settings, Policy::default());
+    if client.is_err() {
+        return Err(client.err().unwrap());
+    }
+
+    let no_redirect_client = build_client(settings, Policy::none());
+    if no_redirect_client.is_err() {
+        return Err(no_redirect_client.err().unwrap());
+    }
+
+    Ok(HttpClient::new(
+        client.unwrap(),
+        no_redirect_client.unwrap(),
+        Arc::new(LiveTransport),
+    ))
+}
+
+fn build_client(settings: &ClientSettings, redirects: Policy) -> Result<Client, String> {
+    let mut builder = Client::builder()
+        .redirect(redirects)
+        .connect_timeout(Duration::from_secs(settings.connect_timeout))
+        .read_timeout(Duration::from_secs(settings.read_timeout))
+        .user_agent(&settings.user_agent);
+
+    if let Some(proxy) = &settings.proxy {
+        let proxy_result = Proxy::all(proxy);
+        if proxy_result.is_err() {
+            let error = proxy_result.err().unwrap();
+            return Err(format!("Couldn't use proxy {proxy}! Error: {error}"));
+        }
+
+        builder = builder.proxy(proxy_result.unwrap());
+    }
+
+    for filename in &settings.ca_certificates {
+        let read_result = fs::read(filename);
+        if read_result.is_err() {
+            let error = read_result.err().unwrap();
+            return Err(format!(
+                "Couldn't read certificate {filename}! Error: {error}"
+            ));
+        }
+
+        let certificates = Certificate::from_pem_bundle(&read_result.unwrap());
+        if certificates.is_err() {
+            let error = certificates.err().unwrap();
+            return Err(format!(
+                "Couldn't parse certificate {filename}! Error: {error}"
+            ));
+        }
+
+        for certificate in certificates.unwrap() {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    let client = builder.build();
+    if client.is_err() {
+        let error = client.err().unwrap();
+        return Err(format!("Couldn't set up the http client! Error: {error}"));
+    }
+
+    Ok(client.unwrap())
+}
+
+// every request is redacted before it is logged, so the pattern is only compiled once
+static KEY_PARAMETER: OnceLock<Regex> = OnceLock::new();
+
+/// Hides the value of any `key=` parameter, so api keys don't end up in logs.
+pub(crate) fn redact_url(url: &str) -> String {
+    let key = KEY_PARAMETER.get_or_init(|| Regex::new(r"([?&]key=)[^&]*").unwrap());
+    key.replace_all(url, "${1}<hidden>").into_owned()
+}
+
+async fn send(
+    builder: RequestBuilder,
+    client: &HttpClient,
+    method: &str,
+    url: &str,
+) -> Result<Response, String> {
+    // the request is sent by whichever client built it, since they differ in whether they follow redirects
+    let (builder_client, request) = builder.build_split();
+    if request.is_err() {
+        let error = request.err().unwrap();
+        return Err(error.without_url().to_string());
+    }
+
+    wait_for_rate_limit(url).await;
+    let url = redact_url(url);
+    trace(
+        format!("Sending {method} request to {url}"),
+        LogFields::default(),
+    );
+    REQUESTS_SENT.fetch_add(1, Ordering::Relaxed);
+    let started = Instant::now();
+    let result = client
+        .transport
+        .send(&builder_client, request.unwrap())
+        .await;
+    let mut fields = LogFields {
+        url: Some(url.clone()),
+        ..LogFields::since(started)
+    };
+    match &result {
+        Ok(response) => {
+            fields.status = Some(response.status().as_u16());
+            debug(
+                format!("{method} {url} responded with {}", response.status()),
+                fields,
+            );
+        }
+        Err(error) => debug(format!("{method} {url} failed! Error: {error}"), fields),
+    }
+
+    result
+}
 
 pub(crate) struct GetRequest {
     pub url: String,
     pub accept: Option<String>,
 }
 
-pub(crate) async fn get_request(request: GetRequest, client: &Client) -> Result<Response, Error> {
-    let mut get_builder = client.get(request.url);
-    if request.accept.is_some() {
-        get_builder = get_builder.header("Accept", request.accept.unwrap());
+pub(crate) async fn get_request(
+    request: GetRequest,
+    client: &HttpClient,
+) -> Result<Response, String> {
+    let mut get_builder = client.client.get(&request.url);
+    if let Some(accept) = request.accept {
+        get_builder = get_builder.header("Accept", accept);
     }
 
-    return get_builder.send().await;
+    send(get_builder, client, "GET", &request.url).await
+}
+
+/// Sends a HEAD request without following redirects, so the response says whether the url redirects.
+pub(crate) async fn head_request(url: &str, client: &HttpClient) -> Result<Response, String> {
+    let head_builder = client.no_redirect_client.head(url);
+    send(head_builder, client, "HEAD", url).await
 }
 
 pub(crate) struct PostJSONRequest<T> {
     pub url: String,
     pub accept: Option<String>,
+    pub authorization: Option<String>,
     pub json: T,
 }
 
 pub(crate) async fn post_json_request<T: Serialize>(
     request: PostJSONRequest<T>,
-    client: &Client,
-) -> Result<Response, Error> {
+    client: &HttpClient,
+) -> Result<Response, String> {
     let mut post_builder = client
-        .post(request.url)
+        .client
+        .post(&request.url)
         .header("Content-Type", "application/json")
         .json(&request.json);
-    if request.accept.is_some() {
-        post_builder = post_builder.header("Accept", request.accept.unwrap());
+    if let Some(accept) = request.accept {
+        post_builder = post_builder.header("Accept", accept);
+    }
+
+    if let Some(authorization) = request.authorization {
+        post_builder = post_builder.header("Authorization", authorization);
+    }
+
+    send(post_builder, client, "POST", &request.url).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_api_keys() {
+        assert_eq!(
+            redact_url("https://api.example.com/videos?part=id&key=secret&id=abc"),
+            "https://api.example.com/videos?part=id&key=<hidden>&id=abc"
+        );
+        assert_eq!(
+            redact_url("https://api.example.com/noKey/videos?part=id"),
+            "https://api.example.com/noKey/videos?part=id"
+        );
     }
 
-    return post_builder.send().await;
+    #[test]
+    fn token_buckets_wait_once_empty() {
+        let mut bucket = TokenBucket {
+            rate: 2.0,
+            tokens: 2.0,
+            updated: Instant::now(),
+        };
+        assert!(bucket.take().is_none());
+        assert!(bucket.take().is_none());
+
+        let wait = bucket.take().unwrap();
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+    }
 }
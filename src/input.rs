@@ -0,0 +1,80 @@
+/*
+    File used to read lists of URLs to archive, either from the `--url` argument or an input file.
+*/
+use std::io::Read;
+
+#[derive(Debug, PartialEq)]
+pub enum InputKind {
+    Video,
+    Playlist,
+    Channel,
+}
+
+pub fn get_input_kind(url: &str) -> InputKind {
+    if url.contains("@") {
+        InputKind::Channel
+    } else if url.contains("list=") && !url.contains("watch?v=") && !url.contains("youtu.be/") {
+        // watch urls with a list= are treated as the single video being watched
+        InputKind::Playlist
+    } else {
+        InputKind::Video
+    }
+}
+
+pub fn read_input_file(path: &str) -> Result<Vec<String>, String> {
+    let mut contents = String::new();
+    if path == "-" {
+        let read_result = std::io::stdin().read_to_string(&mut contents);
+        if read_result.is_err() {
+            let error = read_result.err().unwrap();
+            return Err(format!("Couldn't read URLs from stdin! Error: {error}"));
+        }
+    } else {
+        let read_result = std::fs::read_to_string(path);
+        if read_result.is_err() {
+            let error = read_result.err().unwrap();
+            return Err(format!(
+                "Couldn't read URLs from file {path}! Error: {error}"
+            ));
+        }
+
+        contents = read_result.unwrap();
+    }
+
+    Ok(parse_input(&contents))
+}
+
+/// Reads one URL per line, ignoring blank lines and `#` comments.
+/// Comments can either take up a whole line or follow a URL after some whitespace.
+pub fn parse_input(contents: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for line in contents.lines() {
+        let mut url = line.trim();
+        let comment = url.char_indices().find(|(index, character)| {
+            *character == '#' && url[..*index].ends_with(char::is_whitespace)
+        });
+        if let Some((comment, _)) = comment {
+            url = url.split_at(comment).0.trim_end();
+        }
+
+        if url.is_empty() || url.starts_with("#") {
+            continue;
+        }
+
+        urls.push(String::from(url));
+    }
+
+    urls
+}
+
+pub fn split_url_list(list: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for url in list.split(",") {
+        let url = url.trim();
+        if !url.is_empty() {
+            urls.push(String::from(url));
+        }
+    }
+
+    urls
+}
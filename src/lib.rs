@@ -0,0 +1,37 @@
+/*
+    File used as the root of the library, which the command line in main.rs is a thin wrapper around.
+    Other programs can embed the archiver by setting up an `Archiver` with `Archiver::builder()`.
+*/
+pub mod archive;
+pub mod archiver;
+pub mod checksum;
+pub mod cobalt;
+pub mod config;
+pub mod failed;
+pub mod feed;
+pub mod file;
+pub mod filter;
+pub mod fixture;
+pub mod history;
+pub mod http;
+pub mod input;
+pub mod log;
+pub mod nfo;
+pub mod plan;
+pub mod progress;
+pub mod report;
+pub mod serve;
+pub mod shutdown;
+pub mod site;
+pub mod template;
+pub mod watch;
+pub mod youtube;
+pub mod ytdlp;
+
+pub use archiver::{
+    Archiver, ArchiverBuilder, ChannelArchiveRequest, ListArchiveRequest, PlanRequest,
+    PlaylistArchiveRequest, VideoArchiveRequest,
+};
+pub use progress::RunSummary;
+pub use report::{ArtifactOutcome, VideoOutcome, VideoResult};
+pub use watch::WatchRequest;
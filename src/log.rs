@@ -1,13 +1,294 @@
+/*
+    File used for logging, either as coloured banners for people or as JSON lines for log pipelines.
+    Every log goes to stderr, so that stdout is left for output like plans and reports.
+*/
+use chrono::Utc;
+use clap::ValueEnum;
 use colored::*;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::future::Future;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+pub struct LogSettings {
+    pub level: LogLevel,
+    pub format: LogFormat,
+}
+
+struct LogOutput {
+    level: LogLevel,
+    format: LogFormat,
+    stderr_color: bool,
+    stdout_color: bool,
+}
+
+static OUTPUT: OnceLock<LogOutput> = OnceLock::new();
+
+fn get_output() -> &'static LogOutput {
+    OUTPUT.get_or_init(|| create_output(LogLevel::Info, LogFormat::Text))
+}
+
+fn create_output(level: LogLevel, format: LogFormat) -> LogOutput {
+    // https://no-color.org asks for any non empty value to turn colour off
+    let no_color = std::env::var("NO_COLOR").is_ok_and(|value| !value.is_empty());
+    LogOutput {
+        level,
+        format,
+        stderr_color: !no_color && std::io::stderr().is_terminal(),
+        stdout_color: !no_color && std::io::stdout().is_terminal(),
+    }
+}
+
+/// Sets up logging, which only works once and before anything has been logged.
+pub fn configure_logging(settings: LogSettings) -> Result<(), String> {
+    let set_result = OUTPUT.set(create_output(settings.level, settings.format));
+    if set_result.is_err() {
+        return Err(String::from(
+            "Logging has to be set up before anything is logged!",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Works out the level to log at, where every `verbose` shows one level more and every `quiet` one level less.
+pub fn get_log_level(verbose: u8, quiet: u8) -> LogLevel {
+    let levels = [
+        LogLevel::Trace,
+        LogLevel::Debug,
+        LogLevel::Info,
+        LogLevel::Warn,
+        LogLevel::Error,
+    ];
+    let index = (2 + quiet as i32 - verbose as i32).clamp(0, levels.len() as i32 - 1);
+    levels[index as usize]
+}
+
+/// The video being archived and what is being done to it, which every log carries until it changes,
+/// along with the errors logged for it so they can be reported.
+#[derive(Default)]
+struct LogContext {
+    video_id: Option<String>,
+    stage: Option<&'static str>,
+    errors: Vec<String>,
+}
+
+// each task keeps its own context, so videos archived at the same time don't log as each other
+tokio::task_local! {
+    static CONTEXT: RefCell<LogContext>;
+}
+
+/// The errors logged while archiving a video.
+pub struct VideoLog {
+    pub errors: Vec<String>,
+}
+
+/// Marks every log made while running `future` as being about a video,
+/// returning what it returned along with the errors logged for the video.
+pub async fn log_video<F: Future>(id: &str, future: F) -> (F::Output, VideoLog) {
+    let context = LogContext {
+        video_id: Some(String::from(id)),
+        ..Default::default()
+    };
+    CONTEXT
+        .scope(RefCell::new(context), async move {
+            let output = future.await;
+            let errors = CONTEXT.with(|context| std::mem::take(&mut context.borrow_mut().errors));
+            (output, VideoLog { errors })
+        })
+        .await
+}
+
+/// Marks every log as being part of a stage of archiving, like `metadata` or `video`.
+pub fn log_stage(stage: &'static str) {
+    let _ = CONTEXT.try_with(|context| context.borrow_mut().stage = Some(stage));
+}
+
+/// Fields that only some logs have, like how many bytes were written and how long it took.
+#[derive(Default, Serialize)]
+pub struct LogFields {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u128>,
+}
+
+impl LogFields {
+    pub fn since(start: Instant) -> LogFields {
+        LogFields {
+            duration_ms: Some(start.elapsed().as_millis()),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonLog<'a> {
+    timestamp: String,
+    level: LogLevel,
+    kind: &'a str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    video_id: Option<&'a String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stage: Option<&'static str>,
+    #[serde(flatten)]
+    fields: &'a LogFields,
+}
+
+fn color_banner(kind: &str) -> ColoredString {
+    let banner = format!(" {} ", kind.to_uppercase()).black();
+    match kind {
+        "trace" => banner.on_white(),
+        "debug" => banner.on_bright_blue(),
+        "request" => banner.on_cyan(),
+        "success" => banner.on_green(),
+        "warn" | "retry" => banner.on_bright_yellow(),
+        "plan" => banner.on_yellow(),
+        _ => banner.on_red(),
+    }
+}
+
+/// Logs a message at a level, where `kind` says what sort of message it is, like `request` or `success`.
+pub fn log(level: LogLevel, kind: &str, input: String, fields: LogFields) {
+    let output = get_output();
+    // logs made outside of archiving a video have no context
+    let context = CONTEXT
+        .try_with(|context| {
+            let mut context = context.borrow_mut();
+            if kind == "failure" {
+                context.errors.push(input.clone());
+            }
+            (context.video_id.clone(), context.stage)
+        })
+        .unwrap_or_default();
+
+    if level < output.level {
+        return;
+    }
+
+    match output.format {
+        LogFormat::Json => {
+            let line = JsonLog {
+                timestamp: Utc::now().to_rfc3339(),
+                level,
+                kind,
+                message: &input,
+                video_id: context.0.as_ref(),
+                stage: context.1,
+                fields: &fields,
+            };
+            eprintln!("{}", serde_json::to_string(&line).unwrap());
+        }
+        LogFormat::Text if output.stderr_color => eprintln!("{} {input}", color_banner(kind)),
+        LogFormat::Text => eprintln!("{} {input}", kind.to_uppercase()),
+    }
+}
+
+pub fn trace(input: String, fields: LogFields) {
+    log(LogLevel::Trace, "trace", input, fields);
+}
+
+pub fn debug(input: String, fields: LogFields) {
+    log(LogLevel::Debug, "debug", input, fields);
+}
 
 pub fn request(input: String) {
-    println!("{} {input}", " REQUEST ".black().on_cyan());
+    log(LogLevel::Info, "request", input, LogFields::default());
 }
 
 pub fn success(input: String) {
-    println!("{} {input}", " SUCCESS ".black().on_green());
+    log(LogLevel::Info, "success", input, LogFields::default());
+}
+
+pub fn success_with(input: String, fields: LogFields) {
+    log(LogLevel::Info, "success", input, fields);
+}
+
+pub fn warn(input: String) {
+    log(LogLevel::Warn, "warn", input, LogFields::default());
+}
+
+/// Logs that something failed but is being tried again.
+pub fn retry(input: String) {
+    log(LogLevel::Warn, "retry", input, LogFields::default());
 }
 
 pub fn failure(input: String) {
-    println!("{} {input}", " FAILURE ".black().on_red());
+    log(LogLevel::Error, "failure", input, LogFields::default());
+}
+
+/// Whether progress bars should be drawn, which only makes sense for people watching a terminal.
+pub fn shows_progress() -> bool {
+    let output = get_output();
+    output.format == LogFormat::Text
+        && output.level <= LogLevel::Info
+        && std::io::stderr().is_terminal()
+}
+
+/// Prints a table as it is when logging text, or logs its summary line when logging json.
+pub fn table(lines: &[String], summary: String) {
+    let output = get_output();
+    match output.format {
+        LogFormat::Text if output.level <= LogLevel::Info => {
+            for line in lines {
+                eprintln!("{line}");
+            }
+        }
+        LogFormat::Text => {}
+        LogFormat::Json => log(LogLevel::Info, "summary", summary, LogFields::default()),
+    }
+}
+
+/// Prints a line of a plan to stdout, since the plan is the output of a dry run rather than a log.
+pub fn plan(input: String) {
+    if get_output().stdout_color {
+        println!("{} {input}", color_banner("plan"));
+    } else {
+        println!("PLAN {input}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn keeps_the_errors_of_videos_archived_at_the_same_time_apart() {
+        let first = log_video("first", async {
+            failure(String::from("first failed"));
+            tokio::task::yield_now().await;
+            failure(String::from("first failed again"));
+        });
+        let second = log_video("second", async {
+            tokio::task::yield_now().await;
+            failure(String::from("second failed"));
+        });
+
+        let ((_, first_log), (_, second_log)) = tokio::join!(first, second);
+        assert_eq!(first_log.errors, vec!["first failed", "first failed again"]);
+        assert_eq!(second_log.errors, vec!["second failed"]);
+    }
 }
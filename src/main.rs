@@ -1,24 +1,52 @@
 // crates //
-use clap::{ArgAction, Parser};
-use std::fs::create_dir_all;
-
-// modules //
-mod http;
-mod log;
-use log::*;
-mod cobalt;
-use cobalt::*;
-mod youtube;
-use youtube::*;
-
-#[derive(Parser)]
-#[command(version, about, long_about = None)]
+use chrono::NaiveDate;
+use clap::{
+    error::ErrorKind, ArgAction, ArgGroup, CommandFactory, FromArgMatches, Parser, Subcommand,
+};
+use regex::Regex;
+use std::sync::Arc;
+use tokio::sync::mpsc::unbounded_channel;
+
+// library //
+use youtube_archiver::archive::*;
+use youtube_archiver::checksum::*;
+use youtube_archiver::config::*;
+use youtube_archiver::filter::*;
+use youtube_archiver::fixture::*;
+use youtube_archiver::http::{get_default_user_agent, ClientSettings};
+use youtube_archiver::input::*;
+use youtube_archiver::log::*;
+use youtube_archiver::nfo::*;
+use youtube_archiver::plan::*;
+use youtube_archiver::report::*;
+use youtube_archiver::serve::*;
+use youtube_archiver::shutdown::*;
+use youtube_archiver::site::*;
+use youtube_archiver::youtube::*;
+use youtube_archiver::ytdlp::*;
+use youtube_archiver::*;
+
+#[derive(Parser, Clone)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
+#[command(group(ArgGroup::new("input").args(["url", "input_file"]).required(true)))]
 struct Arguments {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     #[arg(short, long)]
-    url: String,
+    url: Option<String>,
 
     #[arg(short, long)]
-    dir: String,
+    input_file: Option<String>,
+
+    #[arg(short, long, global = true)]
+    dir: Option<String>,
+
+    #[arg(short, long)]
+    template: Option<String>,
+
+    #[arg(long)]
+    file_template: Option<String>,
 
     #[arg(short, long, default_value_t = true, action = ArgAction::Set)]
     video: bool,
@@ -26,107 +54,707 @@ struct Arguments {
     #[arg(short, long, default_value_t = true, action = ArgAction::Set)]
     metadata: bool,
 
+    #[arg(long, value_enum, default_value_t = MetadataFormat::Archiver)]
+    metadata_format: MetadataFormat,
+
+    #[arg(long, value_enum)]
+    nfo: Option<NfoMode>,
+
     #[arg(short, long, default_value_t = true, action = ArgAction::Set)]
     streams_and_premieres: bool,
 
-    #[arg(short, long, default_value_t = String::from("https://yt.lemnoslife.com"))]
+    #[arg(long, action = ArgAction::Set)]
+    streams: Option<bool>,
+
+    #[arg(long, action = ArgAction::Set)]
+    premieres: Option<bool>,
+
+    #[arg(long, action = ArgAction::Set)]
+    upcoming: Option<bool>,
+
+    #[arg(long)]
+    after: Option<NaiveDate>,
+
+    #[arg(long)]
+    before: Option<NaiveDate>,
+
+    #[arg(long)]
+    min_duration: Option<u64>,
+
+    #[arg(long)]
+    max_duration: Option<u64>,
+
+    #[arg(long)]
+    title_match: Option<Regex>,
+
+    #[arg(long)]
+    title_reject: Option<Regex>,
+
+    #[arg(long)]
+    description_match: Option<Regex>,
+
+    #[arg(long)]
+    description_reject: Option<Regex>,
+
+    #[arg(long, value_enum, default_value_t = ShortsFilter::Include)]
+    shorts: ShortsFilter,
+
+    #[arg(long)]
+    min_views: Option<u64>,
+
+    #[arg(short, long, global = true, default_value_t = String::from(archiver::DEFAULT_API))]
     api: String,
+
+    #[arg(long, global = true, hide_default_value = true)]
+    api_key: Option<String>,
+
+    #[arg(long, default_value_t = String::from(archiver::DEFAULT_COBALT))]
+    cobalt: String,
+
+    #[arg(long, hide_default_value = true)]
+    cobalt_key: Option<String>,
+
+    #[arg(long)]
+    dry_run: bool,
+
+    #[arg(long, value_enum, default_value_t = PlanFormat::Text)]
+    plan_format: PlanFormat,
+
+    #[arg(long)]
+    report: Option<String>,
+
+    #[arg(long)]
+    config: Option<String>,
+
+    #[arg(long)]
+    profile: Option<String>,
+
+    #[arg(long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    #[arg(long, global = true, action = ArgAction::Count)]
+    quiet: u8,
+
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    #[arg(long, global = true, default_value_t = 10)]
+    connect_timeout: u64,
+
+    #[arg(long, global = true, default_value_t = 60)]
+    read_timeout: u64,
+
+    #[arg(long, global = true, default_value_t = get_default_user_agent())]
+    user_agent: String,
+
+    #[arg(long, global = true)]
+    proxy: Option<String>,
+
+    #[arg(long, global = true)]
+    ca_certificate: Vec<String>,
+
+    #[arg(long, global = true)]
+    api_rate_limit: Option<f64>,
+
+    #[arg(long, global = true)]
+    cobalt_rate_limit: Option<f64>,
+
+    #[arg(long, global = true)]
+    thumbnail_rate_limit: Option<f64>,
+
+    #[arg(long, global = true, conflicts_with = "replay_http")]
+    record_http: Option<String>,
+
+    #[arg(long, global = true)]
+    replay_http: Option<String>,
+}
+
+#[derive(Subcommand, Clone)]
+enum Commands {
+    #[command(
+        about = "Checks every archived file against its checksum and marks damaged videos to be downloaded again"
+    )]
+    Verify,
+    #[command(about = "Lists archived videos that are no longer available on YouTube")]
+    Report {
+        #[arg(long)]
+        refresh: bool,
+    },
+    #[command(about = "Renders the archive into a static HTML site that can be browsed offline")]
+    RenderSite {
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    #[command(
+        about = "Serves the archive over HTTP, with a JSON api for browsing it and queueing new videos to archive"
+    )]
+    Serve {
+        #[arg(long, default_value_t = String::from("127.0.0.1:8080"))]
+        address: String,
+    },
+    #[command(
+        about = "Archives the videos that failed in earlier runs again, only asking for what failed"
+    )]
+    RetryFailed {
+        #[arg(long)]
+        max_attempts: Option<u32>,
+    },
+    #[command(
+        about = "Keeps checking the channels and playlists given with --url or --input-file, archiving new videos as they're uploaded"
+    )]
+    Watch {
+        #[arg(long, default_value_t = 3600)]
+        interval: u64,
+
+        #[arg(long, default_value_t = 86400)]
+        full_sync_interval: u64,
+    },
+}
+
+impl Arguments {
+    fn get_filters(&self) -> VideoFilters {
+        VideoFilters {
+            streams: self.streams.unwrap_or(self.streams_and_premieres),
+            premieres: self.premieres.unwrap_or(self.streams_and_premieres),
+            upcoming: self.upcoming.unwrap_or(self.streams_and_premieres),
+            after: self.after,
+            before: self.before,
+            min_duration: self.min_duration,
+            max_duration: self.max_duration,
+            title_match: self.title_match.clone(),
+            title_reject: self.title_reject.clone(),
+            description_match: self.description_match.clone(),
+            description_reject: self.description_reject.clone(),
+            shorts: self.shorts.clone(),
+            min_views: self.min_views,
+        }
+    }
+
+    /// Sets up the archiver the rest of the arguments describe.
+    fn archiver(&self) -> Result<Archiver, String> {
+        let mut builder = Archiver::builder()
+            .template(self.template.clone())
+            .file_template(self.file_template.clone())
+            .video(self.video)
+            .metadata(self.metadata)
+            .metadata_format(self.metadata_format.clone())
+            .nfo(self.nfo.clone())
+            .filters(self.get_filters())
+            .api(self.api.clone(), self.api_key.clone())
+            .cobalt(self.cobalt.clone(), self.cobalt_key.clone())
+            .client_settings(ClientSettings {
+                connect_timeout: self.connect_timeout,
+                read_timeout: self.read_timeout,
+                user_agent: self.user_agent.clone(),
+                proxy: self.proxy.clone(),
+                ca_certificates: self.ca_certificate.clone(),
+            })
+            .rate_limits(
+                self.api_rate_limit,
+                self.cobalt_rate_limit,
+                self.thumbnail_rate_limit,
+            );
+        if let Some(dir) = &self.dir {
+            builder = builder.dir(dir);
+        }
+
+        if let Some(directory) = &self.record_http {
+            builder = builder.transport(Arc::new(RecordingTransport {
+                directory: directory.clone(),
+            }));
+        } else if let Some(directory) = &self.replay_http {
+            builder = builder.transport(Arc::new(ReplayTransport {
+                directory: directory.clone(),
+            }));
+        }
+
+        builder.build()
+    }
+}
+
+/// Parses the arguments and sets up the archiver they describe, exiting with an error if either is invalid.
+fn parse_arguments() -> (Arguments, Archiver) {
+    let command_line: Vec<String> = std::env::args().collect();
+    let mut command = Arguments::command();
+
+    let config = load_config(
+        find_argument_value(&command_line, "--config"),
+        find_argument_value(&command_line, "--profile"),
+    );
+    if config.is_err() {
+        let error = config.err().unwrap();
+        command.error(ErrorKind::InvalidValue, error).exit();
+    }
+
+    let config = config.unwrap();
+    if let Some(config) = &config {
+        let config_command = apply_config(command.clone(), &config.values);
+        if config_command.is_err() {
+            let error = config_command.err().unwrap();
+            command.error(ErrorKind::InvalidValue, error).exit();
+        }
+
+        command = config_command.unwrap();
+    }
+
+    let matches = command.get_matches_from(command_line);
+    let args = Arguments::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+
+    // nothing can be logged before this, otherwise logs would ignore the level and format
+    let logging_result = configure_logging(LogSettings {
+        level: get_log_level(args.verbose, args.quiet),
+        format: args.log_format.clone(),
+    });
+    if logging_result.is_err() {
+        let error = logging_result.err().unwrap();
+        failure(error);
+        std::process::exit(1);
+    }
+
+    if let Some(config) = &config {
+        request(format!("Using config file {}", config.path.display()));
+    }
+
+    if args.dir.is_none() {
+        Arguments::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "the following required arguments were not provided:\n  --dir <DIR>",
+            )
+            .exit();
+    }
+
+    let archiver = args.archiver();
+    if archiver.is_err() {
+        let error = archiver.err().unwrap();
+        Arguments::command()
+            .error(ErrorKind::InvalidValue, error)
+            .exit();
+    }
+
+    (args, archiver.unwrap())
 }
 
 fn main() {
-    let args = Arguments::parse();
-
-    tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .unwrap()
-        .block_on(async {
-            if args.url.contains(",") {
-                let videos_raw: Vec<&str> = args.url.split(",").collect();
-                let mut videos: Vec<String> = Vec::new();
-                for raw in videos_raw {
-                    videos.push(String::from(raw));
-                }
+    let (args, archiver) = parse_arguments();
 
-                get_videos(&args, videos).await;
+    // serving has to keep answering requests while jobs do blocking work like checksumming,
+    // so it gets a thread for each core instead of sharing one
+    let mut runtime = match &args.command {
+        Some(Commands::Serve { .. }) => tokio::runtime::Builder::new_multi_thread(),
+        _ => tokio::runtime::Builder::new_current_thread(),
+    };
+    runtime.enable_all().build().unwrap().block_on(async {
+        match &args.command {
+            Some(Commands::Verify) => {
+                verify_archive(&archiver);
+                return;
+            }
+            Some(Commands::Report { refresh }) => {
+                report_unavailable(&archiver, *refresh).await;
+                return;
+            }
+            Some(Commands::RenderSite { output }) => {
+                render_archive_site(&archiver, output);
+                return;
+            }
+            Some(Commands::Serve { address }) => {
+                serve_archive(&archiver, address).await;
+                return;
+            }
+            Some(Commands::RetryFailed { max_attempts }) => {
+                retry_failed(&args, &archiver, *max_attempts).await;
+                return;
+            }
+            Some(Commands::Watch {
+                interval,
+                full_sync_interval,
+            }) => {
+                watch_lists(&args, &archiver, *interval, *full_sync_interval).await;
+                return;
+            }
+            None => {}
+        }
+
+        if let Some(input_file) = &args.input_file {
+            let urls = read_input_file(input_file);
+            if urls.is_err() {
+                let error = urls.err().unwrap();
+                failure(error);
+                std::process::exit(1);
+            }
+
+            if args.dry_run {
+                plan_videos(&args, &archiver, urls.unwrap(), true).await;
             } else {
-                if args.url.contains("@") {
-                    get_channel(&args).await;
-                } else {
-                    get_video(&args).await;
+                let summary = archiver
+                    .archive_list(ListArchiveRequest {
+                        urls: urls.unwrap(),
+                    })
+                    .await;
+                finish_run(&args, summary);
+            }
+            return;
+        }
+
+        let url = args.url.as_ref().unwrap();
+        if args.dry_run {
+            let in_list = url.contains(",") || get_input_kind(url) != InputKind::Video;
+            plan_videos(&args, &archiver, split_url_list(url), in_list).await;
+            return;
+        }
+
+        let summary = if url.contains(",") {
+            archiver
+                .archive_list(ListArchiveRequest {
+                    urls: split_url_list(url),
+                })
+                .await
+        } else {
+            match get_input_kind(url) {
+                InputKind::Channel => {
+                    archiver
+                        .archive_channel(ChannelArchiveRequest { url: url.clone() })
+                        .await
+                }
+                InputKind::Playlist => {
+                    archiver
+                        .archive_playlist(PlaylistArchiveRequest { url: url.clone() })
+                        .await
+                }
+                InputKind::Video => {
+                    let mut summary = RunSummary::new();
+                    summary.add(
+                        archiver
+                            .archive_video(VideoArchiveRequest {
+                                url: url.clone(),
+                                in_list: false,
+                            })
+                            .await,
+                    );
+                    summary
                 }
             }
-        });
+        };
+        finish_run(&args, summary);
+    });
+}
+
+async fn plan_videos(args: &Arguments, archiver: &Archiver, urls: Vec<String>, in_list: bool) {
+    let archive_plan = archiver.plan(PlanRequest { urls, in_list }).await;
+    if archive_plan.is_err() {
+        let error = archive_plan.err().unwrap();
+        failure(error);
+        return;
+    }
+
+    print_plan(&archive_plan.unwrap(), &args.plan_format);
 }
 
-async fn get_channel(args: &Arguments) {
-    let videos = request_channel(ChannelRequest {
-        url: &args.url,
-        api: args.api.clone(),
-        include_streams_and_premieres: args.streams_and_premieres,
-    })
-    .await;
-    if videos.is_err() {
-        let error = videos.err().unwrap();
+/// Prints the summary of a run and writes its report, exiting with a code that says how the run went.
+fn finish_run(args: &Arguments, summary: RunSummary) {
+    summary.print();
+    if let Some(report) = &args.report {
+        let write_result = write_report(report, &summary);
+        if write_result.is_err() {
+            let error = write_result.err().unwrap();
+            failure(format!("Couldn't write the report! Error: {error}"));
+        } else {
+            success(format!("Wrote the report of this run to {report}"));
+        }
+    }
+
+    std::process::exit(summary.exit_code());
+}
+
+/// Verifies every video in the archive, marking any with missing or damaged files in the index
+/// and adding them to the failed queue so that they are downloaded again, and exits with an error if any were found.
+fn verify_archive(archiver: &Archiver) {
+    let index_result = ArchiveIndex::load_all(archiver.dir());
+    if index_result.is_err() {
+        let error = index_result.err().unwrap();
+        failure(format!("Couldn't verify the archive! Error: {error}"));
+        std::process::exit(1);
+    }
+
+    let mut index = index_result.unwrap();
+    request(format!("Verifying {} videos.", index.videos.len()));
+    let mut damaged_videos = 0;
+    for entry in index.videos.values_mut() {
+        let problems = verify_video(archiver.dir(), entry);
+        if problems.is_err() {
+            let error = problems.err().unwrap();
+            failure(format!(
+                "Couldn't verify video {}! Error: {error}",
+                entry.id
+            ));
+            damaged_videos += 1;
+            continue;
+        }
+
+        let problems = problems.unwrap();
+        for (artifact, problem) in &problems {
+            failure(format!(
+                "The {artifact} file of video {} {}!",
+                entry.id,
+                problem.describe()
+            ));
+            if !entry.damaged.contains(artifact) {
+                entry.damaged.push(artifact.clone());
+            }
+        }
+
+        if !problems.is_empty() {
+            damaged_videos += 1;
+        }
+    }
+
+    let save_result = index
+        .save(archiver.dir())
+        .and_then(|_| archiver.queue_damaged(&index));
+    if save_result.is_err() {
+        let error = save_result.err().unwrap();
+        failure(error);
+        std::process::exit(1);
+    }
+
+    if damaged_videos > 0 {
         failure(format!(
-            "Encountered an error while getting channel videos! Error: {error}"
+            "Found problems with {damaged_videos} of {} videos, which will be downloaded again by retry-failed or the next run.",
+            index.videos.len()
         ));
-        return;
+        std::process::exit(1);
     }
 
-    for video in videos.unwrap() {
-        let passed_args = Arguments {
-            url: video.clone(),
-            dir: format!("{}/{}", args.dir, get_id_from_url(&video)),
-            video: args.video,
-            metadata: args.metadata,
-            streams_and_premieres: args.streams_and_premieres,
-            api: args.api.clone(),
-        };
-        get_video(&passed_args).await;
+    success(format!(
+        "All {} videos in the archive are intact!",
+        index.videos.len()
+    ));
+}
+
+/// Lists every archived video that is no longer on youtube,
+/// checking every video in the archive again first when refreshing.
+async fn report_unavailable(archiver: &Archiver, refresh: bool) {
+    let index_result = ArchiveIndex::load_all(archiver.dir());
+    if index_result.is_err() {
+        let error = index_result.err().unwrap();
+        failure(format!("Couldn't read the archive! Error: {error}"));
+        std::process::exit(1);
+    }
+
+    let mut index = index_result.unwrap();
+    if refresh {
+        request(format!(
+            "Checking whether {} archived videos are still available.",
+            index.videos.len()
+        ));
+        let ids: Vec<String> = index.videos.keys().cloned().collect();
+        let unavailable = request_unavailable_videos(&ids, archiver.youtube_api()).await;
+        if unavailable.is_err() {
+            let error = unavailable.err().unwrap();
+            failure(error);
+            std::process::exit(1);
+        }
+
+        let unavailable = unavailable.unwrap();
+        for id in &ids {
+            let reason = unavailable.get(id).copied();
+            if index.set_availability(id, reason) && reason.is_none() {
+                success(format!("Video {id} is available on YouTube again!"));
+            }
+        }
+
+        let save_result = index.save(archiver.dir());
+        if save_result.is_err() {
+            let error = save_result.err().unwrap();
+            failure(error);
+            std::process::exit(1);
+        }
+    }
+
+    let mut count = 0;
+    for entry in index.videos.values() {
+        if let Some(unavailable) = &entry.unavailable {
+            count += 1;
+            println!(
+                "{}\t{}\t{}\t{} since {} (last checked {})",
+                entry.id,
+                entry.channel,
+                entry.title,
+                unavailable.reason.describe(),
+                unavailable.first_seen,
+                unavailable.last_checked
+            );
+        }
     }
 
-    success(String::from("Finished downloading all videos from provided channel! Check for any potential errors in the console just in case."));
+    success(format!(
+        "{count} of {} archived videos are no longer available on YouTube.",
+        index.videos.len()
+    ));
 }
 
-async fn get_videos(args: &Arguments, videos: Vec<String>) {
-    request(format!("Downloading all videos from list {:?}", &videos));
-    for video in videos {
-        let passed_args = Arguments {
-            url: video.clone(),
-            dir: format!("{}/{}", args.dir, get_id_from_url(&video)),
-            video: args.video,
-            metadata: args.metadata,
-            streams_and_premieres: args.streams_and_premieres,
-            api: args.api.clone(),
-        };
-        get_video(&passed_args).await;
+fn render_archive_site(archiver: &Archiver, output: &Option<String>) {
+    let index_result = ArchiveIndex::load_all(archiver.dir());
+    if index_result.is_err() {
+        let error = index_result.err().unwrap();
+        failure(format!("Couldn't read the archive! Error: {error}"));
+        std::process::exit(1);
     }
 
-    success(String::from("Finished downloading all videos from provided channel! Check for any potential errors in the console just in case."));
+    let output = match output {
+        Some(output) => output.clone(),
+        None => format!("{}/site", archiver.dir()),
+    };
+    request(format!("Rendering the archive into {output}."));
+    let render_result = render_site(archiver.dir(), &output, &index_result.unwrap());
+    if render_result.is_err() {
+        let error = render_result.err().unwrap();
+        failure(format!("Couldn't render the archive! Error: {error}"));
+        std::process::exit(1);
+    }
 }
 
-async fn get_video(args: &Arguments) {
-    // create directory for video if it doesn't already exist
-    create_dir_all(&args.dir).unwrap();
+/// Serves the archive while archiving any jobs submitted through the api, one at a time.
+async fn serve_archive(archiver: &Archiver, address: &str) {
+    let index = ServedIndex::load(archiver.dir()).await;
+    if index.is_err() {
+        let error = index.err().unwrap();
+        failure(error);
+        std::process::exit(1);
+    }
+
+    let index = Arc::new(index.unwrap());
+    let queue = Arc::new(JobQueue::default());
+    let (sender, mut receiver) = unbounded_channel::<Job>();
+    let mut server = tokio::spawn(serve(ServeParameters {
+        root: archiver.dir().clone(),
+        address: String::from(address),
+        index: index.clone(),
+        queue: queue.clone(),
+        sender,
+    }));
+    success(format!(
+        "Serving the archive in {} on http://{address}",
+        archiver.dir()
+    ));
+
+    loop {
+        tokio::select! {
+            result = &mut server => {
+                match result {
+                    Ok(Err(error)) => failure(error),
+                    Err(error) => failure(format!("The server stopped! Error: {error}")),
+                    Ok(Ok(())) => {}
+                }
+                std::process::exit(1);
+            }
+            Some(job) = receiver.recv() => {
+                queue.set_status(job.id, JobStatus::Running);
+                request(format!("Starting job {} for {}", job.id, job.url));
+                // jobs run in their own task so that one going wrong can't take the server down with it
+                let job_archiver = archiver.clone();
+                let url = job.url.clone();
+                let result = tokio::spawn(async move {
+                    job_archiver
+                        .archive_list(ListArchiveRequest { urls: vec![url] })
+                        .await
+                })
+                .await;
+
+                // the index is read again before the job is marked done, so its videos show up as it finishes
+                let refresh_result = index.refresh().await;
+                if refresh_result.is_err() {
+                    let error = refresh_result.err().unwrap();
+                    warn(format!("Couldn't update the index after job {}! Error: {error}", job.id));
+                }
 
-    if args.metadata {
-        request_metadata(
-            MetadataParameters {
-                url: &args.url,
-                dir: &args.dir,
+                match result {
+                    Ok(summary) => {
+                        summary.print();
+                        queue.set_status(job.id, JobStatus::Finished);
+                    }
+                    Err(error) => {
+                        failure(format!("Job {} for {} failed! Error: {error}", job.id, job.url));
+                        queue.set_status(job.id, JobStatus::Failed);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Watches the channels and playlists given with --url or --input-file until stopped.
+async fn watch_lists(
+    args: &Arguments,
+    archiver: &Archiver,
+    interval: u64,
+    full_sync_interval: u64,
+) {
+    let urls = match (&args.input_file, &args.url) {
+        (Some(input_file), _) => read_input_file(input_file),
+        (None, Some(url)) => Ok(split_url_list(url)),
+        (None, None) => Err(String::from(
+            "Provide the channels and playlists to watch with --url or --input-file!",
+        )),
+    };
+    if urls.is_err() {
+        let error = urls.err().unwrap();
+        failure(error);
+        std::process::exit(1);
+    }
+
+    archiver
+        .watch(
+            WatchRequest {
+                urls: urls.unwrap(),
+                interval,
+                full_sync_interval,
             },
-            args.api.clone(),
+            listen_for_shutdown(),
         )
         .await;
+}
+
+async fn retry_failed(args: &Arguments, archiver: &Archiver, max_attempts: Option<u32>) {
+    let summary = archiver.retry_failed(max_attempts).await;
+    if summary.is_err() {
+        let error = summary.err().unwrap();
+        failure(error);
+        std::process::exit(1);
     }
 
-    if args.video {
-        request_video(VideoParameters {
-            url: &args.url,
-            video_codec: String::from("h264"),
-            filename: format!("{}/source_h264.mp4", &args.dir),
-        })
-        .await;
+    if let Some(summary) = summary.unwrap() {
+        finish_run(args, summary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn takes_the_input_file_from_a_config() {
+        let filename = std::env::temp_dir().join(format!(
+            "youtube_archiver_config_{}.toml",
+            std::process::id()
+        ));
+        fs::write(&filename, "dir = \"archive\"\ninput_file = \"list.txt\"\n").unwrap();
+        let config = load_config(Some(filename.to_string_lossy().to_string()), None)
+            .unwrap()
+            .unwrap();
+        let _ = fs::remove_file(&filename);
+
+        let command = apply_config(Arguments::command(), &config.values).unwrap();
+        let matches = command.try_get_matches_from(["youtube_archiver"]).unwrap();
+        let args = Arguments::from_arg_matches(&matches).unwrap();
+        assert_eq!(args.input_file.as_deref(), Some("list.txt"));
+        assert!(args.url.is_none());
+
+        // without the config, one of them still has to be given
+        assert!(Arguments::command()
+            .try_get_matches_from(["youtube_archiver", "--dir", "archive"])
+            .is_err());
     }
 }
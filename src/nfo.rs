@@ -0,0 +1,208 @@
+/*
+    File used to write the NFO files and artwork that media servers like Jellyfin and Kodi
+    read to show videos with their proper titles, descriptions and thumbnails.
+*/
+use crate::archive::*;
+use crate::file::*;
+use crate::log::*;
+use crate::youtube::*;
+use chrono::{DateTime, Datelike, FixedOffset};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+pub const NFO_ARTIFACT: &str = "nfo";
+pub const ARTWORK_ARTIFACTS: [&str; 2] = ["poster", "fanart"];
+const SHOW_FILENAME: &str = "tvshow.nfo";
+
+#[derive(Debug, Clone, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NfoMode {
+    Movie,
+    Episode,
+}
+
+pub struct NfoParameters<'a> {
+    pub item: &'a ItemResponse,
+    pub paths: &'a ArchivePaths,
+    pub mode: &'a NfoMode,
+    // folder relative to the archive root holding every video of the channel, if the template has one
+    pub show_dir: Option<String>,
+}
+
+pub fn escape_xml(input: &str) -> String {
+    // control characters apart from tabs and newlines aren't allowed anywhere in XML, even escaped
+    let allowed: String = input
+        .chars()
+        .filter(|character| {
+            matches!(character, '\t' | '\n' | '\r')
+                || (!character.is_control() && !matches!(character, '\u{FFFE}' | '\u{FFFF}'))
+        })
+        .collect();
+    allowed
+        .replace("&", "&amp;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
+        .replace("\"", "&quot;")
+        .replace("'", "&apos;")
+}
+
+fn element(name: &str, value: &str) -> String {
+    format!("  <{name}>{}</{name}>\n", escape_xml(value))
+}
+
+/// Numbers a video by when it was published within its year, down to the second,
+/// so that videos published on the same day still get their own episode in upload order.
+/// For example, a video published on the 1st of May at 12:30:00 is episode 501123000.
+fn get_episode_number(published: &DateTime<FixedOffset>) -> u32 {
+    published
+        .format("%m%d%H%M%S")
+        .to_string()
+        .parse()
+        .unwrap_or(0)
+}
+
+fn create_video_nfo(item: &ItemResponse, mode: &NfoMode) -> String {
+    let snippet = &item.snippet;
+    let published = DateTime::parse_from_rfc3339(&snippet.publishedAt).ok();
+    let root = match mode {
+        NfoMode::Movie => "movie",
+        NfoMode::Episode => "episodedetails",
+    };
+
+    let mut contents =
+        format!("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<{root}>\n");
+    contents += &element("title", &snippet.title);
+    contents += &element("plot", &snippet.description);
+    if let Some(published) = published {
+        let date = published.format("%Y-%m-%d").to_string();
+        contents += &element("premiered", &date);
+        contents += &element("aired", &date);
+        contents += &element("year", &published.year().to_string());
+        if *mode == NfoMode::Episode {
+            // youtube has no seasons, so videos are grouped into a season per year and numbered by upload time within it
+            contents += &element("season", &published.year().to_string());
+            contents += &element("episode", &get_episode_number(&published).to_string());
+        }
+    }
+
+    match mode {
+        NfoMode::Movie => {
+            contents += &element("studio", &snippet.channelTitle);
+            contents += &element("director", &snippet.channelTitle);
+        }
+        NfoMode::Episode => contents += &element("showtitle", &snippet.channelTitle),
+    }
+
+    if let Some(details) = &item.contentDetails {
+        let minutes = parse_duration(&details.duration).div_ceil(60);
+        contents += &element("runtime", &minutes.to_string());
+    }
+
+    for tag in snippet.tags.iter().flatten() {
+        contents += &element("tag", tag);
+    }
+
+    contents += &format!(
+        "  <uniqueid type=\"youtube\" default=\"true\">{}</uniqueid>\n",
+        escape_xml(&item.id)
+    );
+    contents += &format!("</{root}>\n");
+    contents
+}
+
+fn create_show_nfo(item: &ItemResponse) -> String {
+    let snippet = &item.snippet;
+    let mut contents =
+        String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<tvshow>\n");
+    contents += &element("title", &snippet.channelTitle);
+    contents += &element("studio", &snippet.channelTitle);
+    if let Some(channel_id) = &snippet.channelId {
+        contents += &format!(
+            "  <uniqueid type=\"youtube\" default=\"true\">{}</uniqueid>\n",
+            escape_xml(channel_id)
+        );
+    }
+
+    contents += "</tvshow>\n";
+    contents
+}
+
+/// Writes the NFO file and artwork of a video, plus the NFO of its channel when writing episodes,
+/// returning the artifacts that were written successfully.
+pub fn write_nfo(params: NfoParameters<'_>) -> Vec<String> {
+    let mut written: Vec<String> = Vec::new();
+    let filename = params.paths.get_video_file("source_h264", NFO_ARTIFACT);
+    let write_result = write_atomic(
+        &filename,
+        create_video_nfo(params.item, params.mode).as_bytes(),
+    );
+    if write_result.is_err() {
+        let error = write_result.err().unwrap();
+        failure(error);
+        return written;
+    }
+
+    success(format!("Wrote to requested file {filename} successfully!"));
+    written.push(String::from(NFO_ARTIFACT));
+
+    // the artwork is the largest thumbnail that was downloaded
+    let thumbnail = THUMBNAIL_SIZES
+        .iter()
+        .rev()
+        .map(|size| params.paths.get_file(&format!("thumb_{size}"), "jpg"))
+        .find_map(|filename| fs::read(filename).ok());
+    match thumbnail {
+        Some(thumbnail) => {
+            for artifact in ARTWORK_ARTIFACTS {
+                let filename = params.paths.get_file(artifact, "jpg");
+                let write_result = write_atomic(&filename, &thumbnail);
+                if write_result.is_err() {
+                    let error = write_result.err().unwrap();
+                    failure(error);
+                    continue;
+                }
+
+                written.push(String::from(artifact));
+            }
+        }
+        None => failure(format!(
+            "Video {} has no thumbnails to use as its poster and fanart!",
+            params.item.id
+        )),
+    }
+
+    if *params.mode == NfoMode::Episode {
+        if let Some(show_dir) = &params.show_dir {
+            let filename = format!("{}/{SHOW_FILENAME}", params.paths.get_full_path(show_dir));
+            let write_result = write_atomic(&filename, create_show_nfo(params.item).as_bytes());
+            if write_result.is_err() {
+                let error = write_result.err().unwrap();
+                failure(error);
+            }
+        }
+    }
+
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_episodes_by_upload_time() {
+        let first = DateTime::parse_from_rfc3339("2024-05-01T09:15:00Z").unwrap();
+        let second = DateTime::parse_from_rfc3339("2024-05-01T12:30:00Z").unwrap();
+        assert_eq!(get_episode_number(&first), 501091500);
+        assert_eq!(get_episode_number(&second), 501123000);
+    }
+
+    #[test]
+    fn escapes_xml() {
+        assert_eq!(
+            escape_xml("Tom & \"Jerry\" <3\u{0}\u{1b}\n"),
+            "Tom &amp; &quot;Jerry&quot; &lt;3\n"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+/*
+    File used for dry runs, which work out everything an archive run would do without downloading anything.
+*/
+use crate::archive::*;
+use crate::log::*;
+use crate::template::*;
+use crate::youtube::*;
+use crate::ytdlp::*;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum PlanFormat {
+    Text,
+    Json,
+}
+
+#[derive(Serialize)]
+pub struct Plan {
+    pub videos: Vec<PlannedVideo>,
+    pub api_calls: PlannedApiCalls,
+}
+
+#[derive(Serialize)]
+pub struct PlannedVideo {
+    pub id: String,
+    pub url: String,
+    // new, partial or archived
+    pub state: String,
+    // folder relative to the archive root, unknown until metadata is fetched for templates that need it
+    pub dir: Option<String>,
+    pub existing_artifacts: Vec<String>,
+    pub actions: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct PlannedApiCalls {
+    // calls already made to list the videos of channels and playlists
+    pub enumeration: usize,
+    pub metadata: usize,
+    pub cobalt: usize,
+    // thumbnails come from youtube's image servers rather than an api, and not every video has all five sizes
+    pub max_thumbnails: usize,
+    pub total: usize,
+}
+
+pub struct PlanParameters<'a> {
+    pub videos: &'a [String],
+    pub root: &'a String,
+    pub folder_template: &'a Template,
+    pub file_template: &'a Template,
+    pub metadata: bool,
+    pub video: bool,
+    pub enumeration_calls: usize,
+}
+
+pub fn create_plan(params: PlanParameters<'_>, index: &ArchiveIndex) -> Plan {
+    let needs_metadata =
+        params.folder_template.needs_metadata() || params.file_template.needs_metadata();
+    let mut videos: Vec<PlannedVideo> = Vec::new();
+    let mut metadata_calls = 0;
+    let mut cobalt_calls = 0;
+    let mut max_thumbnails = 0;
+    for url in params.videos {
+        let id = match get_id_from_url(url) {
+            Ok(id) => id,
+            Err(error) => {
+                warn(format!("Leaving {url} out of the plan! Error: {error}"));
+                continue;
+            }
+        };
+        let entry = index.videos.get(&id);
+
+        let mut existing_artifacts: Vec<String> = Vec::new();
+        let mut dir: Option<String> = None;
+        if let Some(entry) = entry {
+            let paths = ArchivePaths::from_entry(params.root, entry);
+            for (artifact, relative) in paths.get_known_artifacts() {
+                // damaged files will be downloaded again, so they don't count as archived
+                if Path::new(&paths.get_full_path(&relative)).exists()
+                    && !entry.damaged.contains(&artifact)
+                {
+                    existing_artifacts.push(artifact);
+                }
+            }
+
+            dir = Some(entry.dir.clone());
+        } else if !needs_metadata {
+            dir = Some(
+                params
+                    .folder_template
+                    .render(&TemplateFields::from_id(id.clone())),
+            );
+        }
+
+        let has_video = existing_artifacts
+            .iter()
+            .any(|artifact| artifact.starts_with("source_"));
+        let has_metadata = existing_artifacts
+            .iter()
+            .any(|artifact| artifact == "meta" || artifact == INFO_JSON_ARTIFACT);
+
+        let mut actions: Vec<String> = Vec::new();
+        if params.metadata || needs_metadata {
+            metadata_calls += 1;
+        }
+
+        if params.metadata {
+            max_thumbnails += THUMBNAIL_SIZES.len();
+            actions.push(String::from(if has_metadata {
+                "update metadata and thumbnails"
+            } else {
+                "download metadata and thumbnails"
+            }));
+        }
+
+        if params.video {
+            cobalt_calls += 1;
+            actions.push(String::from(if has_video {
+                "download video again"
+            } else {
+                "download video"
+            }));
+        }
+
+        let complete = (!params.metadata || has_metadata) && (!params.video || has_video);
+        let state = if entry.is_none() {
+            "new"
+        } else if complete {
+            "archived"
+        } else {
+            "partial"
+        };
+
+        videos.push(PlannedVideo {
+            id,
+            url: url.clone(),
+            state: String::from(state),
+            dir,
+            existing_artifacts,
+            actions,
+        });
+    }
+
+    Plan {
+        videos,
+        api_calls: PlannedApiCalls {
+            enumeration: params.enumeration_calls,
+            metadata: metadata_calls,
+            cobalt: cobalt_calls,
+            max_thumbnails,
+            total: params.enumeration_calls + metadata_calls + cobalt_calls,
+        },
+    }
+}
+
+pub fn print_plan(archive_plan: &Plan, format: &PlanFormat) {
+    match format {
+        PlanFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(archive_plan).unwrap());
+        }
+        PlanFormat::Text => {
+            for video in &archive_plan.videos {
+                let dir = match &video.dir {
+                    Some(dir) if dir.is_empty() => String::from("the archive root"),
+                    Some(dir) => dir.clone(),
+                    None => String::from("a folder decided once its metadata is fetched"),
+                };
+                let actions = if video.actions.is_empty() {
+                    String::from("nothing to do")
+                } else {
+                    video.actions.join(", ")
+                };
+                plan(format!(
+                    "{} ({}) into {dir}: {actions}",
+                    video.id, video.state
+                ));
+            }
+
+            let calls = &archive_plan.api_calls;
+            plan(format!(
+                "{} videos planned. Estimated api calls: {} in total, {} already made to list videos, {} for metadata and {} to cobalt, plus up to {} thumbnail downloads.",
+                archive_plan.videos.len(),
+                calls.total,
+                calls.enumeration,
+                calls.metadata,
+                calls.cobalt,
+                calls.max_thumbnails
+            ));
+        }
+    }
+}
@@ -0,0 +1,165 @@
+/*
+    File used to show how archiving is going, with progress bars for downloads
+    and a summary of every video once a run has finished.
+*/
+use crate::log::*;
+use crate::report::*;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::time::{Duration, Instant};
+
+/// Creates a progress bar for a download, which is hidden when nobody is watching a terminal.
+/// Downloads without a known length show how much has been downloaded and how fast instead.
+pub fn download_progress(length: Option<u64>, label: String) -> ProgressBar {
+    let bar = match length {
+        Some(length) => ProgressBar::new(length).with_style(
+            ProgressStyle::with_template(
+                "{msg} [{bar:30}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta} left)",
+            )
+            .unwrap()
+            .progress_chars("=> "),
+        ),
+        None => ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template("{spinner} {msg} {bytes} ({bytes_per_sec})").unwrap(),
+        ),
+    };
+
+    if !shows_progress() {
+        bar.set_draw_target(ProgressDrawTarget::hidden());
+    }
+
+    bar.set_message(label);
+    bar.enable_steady_tick(Duration::from_millis(250));
+    bar
+}
+
+pub struct RunSummary {
+    pub started: Instant,
+    pub videos: Vec<VideoResult>,
+    // errors that weren't about any one video, like a page of a channel failing to load
+    pub errors: Vec<String>,
+}
+
+impl Default for RunSummary {
+    fn default() -> RunSummary {
+        RunSummary::new()
+    }
+}
+
+impl RunSummary {
+    pub fn new() -> RunSummary {
+        RunSummary {
+            started: Instant::now(),
+            videos: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, result: VideoResult) {
+        self.videos.push(result);
+    }
+
+    pub fn add_error(&mut self, error: String) {
+        self.errors.push(error);
+    }
+
+    pub fn total(&self) -> usize {
+        self.videos.len()
+    }
+
+    pub fn count(&self, outcome: VideoOutcome) -> usize {
+        self.videos
+            .iter()
+            .filter(|video| video.outcome == outcome)
+            .count()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.videos.iter().map(|video| video.bytes).sum()
+    }
+
+    /// Works out the exit code for the run, where 0 means everything was archived,
+    /// 1 means nothing could be archived and 3 means only some of it could.
+    pub fn exit_code(&self) -> i32 {
+        let archived = self.count(VideoOutcome::Archived) + self.count(VideoOutcome::Partial);
+        let complete = self.count(VideoOutcome::Partial) == 0
+            && self.count(VideoOutcome::Failed) == 0
+            && self.errors.is_empty();
+        if complete {
+            0
+        } else if archived == 0 {
+            1
+        } else {
+            3
+        }
+    }
+
+    pub fn print(&self) {
+        let elapsed = self.started.elapsed().as_secs();
+        let (archived, partial, failed, skipped) = (
+            self.count(VideoOutcome::Archived),
+            self.count(VideoOutcome::Partial),
+            self.count(VideoOutcome::Failed),
+            self.count(VideoOutcome::Skipped),
+        );
+        let rows = [
+            ("Archived", archived.to_string()),
+            ("Partial", partial.to_string()),
+            ("Failed", failed.to_string()),
+            ("Skipped", skipped.to_string()),
+            ("Total", self.total().to_string()),
+            ("Written", format_bytes(self.bytes())),
+            (
+                "Time",
+                format!(
+                    "{}:{:02}:{:02}",
+                    elapsed / 3600,
+                    elapsed / 60 % 60,
+                    elapsed % 60
+                ),
+            ),
+        ];
+
+        let mut lines = vec![String::from("+----------+-------------+")];
+        for (name, value) in rows {
+            lines.push(format!("| {name:<8} | {value:>11} |"));
+        }
+        lines.push(lines[0].clone());
+        let incomplete: Vec<&str> = self
+            .videos
+            .iter()
+            .filter(|video| matches!(video.outcome, VideoOutcome::Partial | VideoOutcome::Failed))
+            .map(|video| video.id.as_str())
+            .collect();
+        if !incomplete.is_empty() {
+            lines.push(format!("Not fully archived: {}", incomplete.join(", ")));
+        }
+
+        table(
+            &lines,
+            format!(
+                "Archived {}, partially archived {}, failed {} and skipped {} videos, writing {}.",
+                archived,
+                partial,
+                failed,
+                skipped,
+                format_bytes(self.bytes())
+            ),
+        );
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", units[unit])
+    }
+}
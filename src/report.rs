@@ -0,0 +1,103 @@
+/*
+    File used to write a machine readable report of a run, with how every video and artifact went,
+    so that scripts and CI can tell what failed without reading the logs.
+*/
+use crate::file::*;
+use crate::progress::*;
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoOutcome {
+    // everything that was asked for was archived
+    Archived,
+    // some of what was asked for was archived, but not all of it
+    Partial,
+    Failed,
+    // the video is no longer available, so there was nothing to archive
+    Skipped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtifactOutcome {
+    Written,
+    Failed,
+}
+
+/// How archiving a video went, before the errors logged along the way are added to it.
+pub struct ArchiveAttempt {
+    pub outcome: VideoOutcome,
+    pub artifacts: BTreeMap<String, ArtifactOutcome>,
+    // size of every file written for the video
+    pub bytes: u64,
+}
+
+impl ArchiveAttempt {
+    pub fn empty(outcome: VideoOutcome) -> ArchiveAttempt {
+        ArchiveAttempt {
+            outcome,
+            artifacts: BTreeMap::new(),
+            bytes: 0,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct VideoResult {
+    pub id: String,
+    pub url: String,
+    pub outcome: VideoOutcome,
+    pub artifacts: BTreeMap<String, ArtifactOutcome>,
+    pub bytes: u64,
+    pub errors: Vec<String>,
+    // how many times the video had failed before this attempt
+    pub retries: u32,
+    pub started_at: String,
+    pub duration_ms: u128,
+}
+
+#[derive(Serialize)]
+struct RunReport<'a> {
+    started_at: String,
+    finished_at: String,
+    duration_ms: u128,
+    exit_code: i32,
+    archived: usize,
+    partial: usize,
+    failed: usize,
+    skipped: usize,
+    bytes: u64,
+    errors: &'a Vec<String>,
+    videos: &'a Vec<VideoResult>,
+}
+
+/// Writes the report of a run as json.
+pub fn write_report(filename: &str, summary: &RunSummary) -> Result<(), String> {
+    let elapsed = summary.started.elapsed();
+    let finished_at = Utc::now();
+    let started_at = finished_at - Duration::from_std(elapsed).unwrap_or_default();
+    let report = RunReport {
+        started_at: started_at.to_rfc3339(),
+        finished_at: finished_at.to_rfc3339(),
+        duration_ms: elapsed.as_millis(),
+        exit_code: summary.exit_code(),
+        archived: summary.count(VideoOutcome::Archived),
+        partial: summary.count(VideoOutcome::Partial),
+        failed: summary.count(VideoOutcome::Failed),
+        skipped: summary.count(VideoOutcome::Skipped),
+        bytes: summary.bytes(),
+        errors: &summary.errors,
+        videos: &summary.videos,
+    };
+
+    let contents = serde_json::to_string_pretty(&report).unwrap();
+    let write_result = write_atomic(filename, contents.as_bytes());
+    if write_result.is_err() {
+        return Err(write_result.err().unwrap());
+    }
+
+    Ok(())
+}
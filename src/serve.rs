@@ -0,0 +1,331 @@
+/*
+    File used to serve an archive over HTTP, with its files (including range requests for video streaming),
+    a JSON api for listing and searching videos and channels, and a queue of new archive jobs.
+*/
+use crate::archive::*;
+use crate::failed::*;
+use crate::youtube::*;
+use axum::{
+    body::Body,
+    extract::{Path, Query, Request, State},
+    http::{StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, RwLock},
+};
+use tokio::sync::mpsc::UnboundedSender;
+use tower_http::services::ServeDir;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Finished,
+    // the job stopped part way through, rather than archiving with errors
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: u64,
+    pub url: String,
+    pub status: JobStatus,
+}
+
+/// Jobs submitted through the api, which are archived one at a time in the order they came in.
+#[derive(Default)]
+pub struct JobQueue {
+    jobs: Mutex<Vec<Job>>,
+}
+
+impl JobQueue {
+    fn add(&self, url: String) -> Job {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = Job {
+            id: jobs.len() as u64 + 1,
+            url,
+            status: JobStatus::Queued,
+        };
+        jobs.push(job.clone());
+        job
+    }
+
+    pub fn set_status(&self, id: u64, status: JobStatus) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.iter_mut().find(|job| job.id == id) {
+            job.status = status;
+        }
+    }
+
+    fn list(&self) -> Vec<Job> {
+        self.jobs.lock().unwrap().clone()
+    }
+}
+
+/// The index of the archive being served, which is only read again once a job finishes,
+/// since reading it walks every folder of the archive.
+pub struct ServedIndex {
+    root: String,
+    index: RwLock<Arc<ArchiveIndex>>,
+}
+
+// reading the index is blocking work, so it is kept off the threads answering requests
+async fn load_index(root: &str) -> Result<ArchiveIndex, String> {
+    let root = String::from(root);
+    let load_result = tokio::task::spawn_blocking(move || ArchiveIndex::load_all(&root)).await;
+    if load_result.is_err() {
+        let error = load_result.err().unwrap();
+        return Err(format!("Couldn't read the archive! Error: {error}"));
+    }
+
+    load_result.unwrap()
+}
+
+impl ServedIndex {
+    pub async fn load(root: &str) -> Result<ServedIndex, String> {
+        let index = load_index(root).await?;
+        Ok(ServedIndex {
+            root: String::from(root),
+            index: RwLock::new(Arc::new(index)),
+        })
+    }
+
+    /// Reads the index again, so that videos archived while serving show up.
+    pub async fn refresh(&self) -> Result<(), String> {
+        let index = load_index(&self.root).await?;
+        *self.index.write().unwrap() = Arc::new(index);
+        Ok(())
+    }
+
+    fn get(&self) -> Arc<ArchiveIndex> {
+        self.index.read().unwrap().clone()
+    }
+}
+
+struct ServerState {
+    root: String,
+    index: Arc<ServedIndex>,
+    queue: Arc<JobQueue>,
+    // the archiver waits on the other end for jobs to archive
+    sender: UnboundedSender<Job>,
+}
+
+pub struct ServeParameters {
+    pub root: String,
+    pub address: String,
+    pub index: Arc<ServedIndex>,
+    pub queue: Arc<JobQueue>,
+    pub sender: UnboundedSender<Job>,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+fn error_response(status: StatusCode, error: String) -> Response {
+    (status, Json(ErrorResponse { error })).into_response()
+}
+
+#[derive(Deserialize)]
+struct VideoQuery {
+    // words that have to appear in the title, channel or id
+    q: Option<String>,
+    channel: Option<String>,
+}
+
+fn matches_query(entry: &ArchiveEntry, query: &VideoQuery) -> bool {
+    if let Some(channel) = &query.channel {
+        if &entry.channel != channel {
+            return false;
+        }
+    }
+
+    match &query.q {
+        Some(search) => {
+            let text = format!("{} {} {}", entry.title, entry.channel, entry.id).to_lowercase();
+            search
+                .to_lowercase()
+                .split_whitespace()
+                .all(|term| text.contains(term))
+        }
+        None => true,
+    }
+}
+
+async fn list_videos(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<VideoQuery>,
+) -> Response {
+    let index = state.index.get();
+
+    let videos: Vec<&ArchiveEntry> = index
+        .videos
+        .values()
+        .filter(|entry| matches_query(entry, &query))
+        .collect();
+    Json(videos).into_response()
+}
+
+#[derive(Serialize)]
+struct VideoResponse<'a> {
+    #[serde(flatten)]
+    entry: &'a ArchiveEntry,
+    metadata: Option<serde_json::Value>,
+}
+
+async fn get_video(State(state): State<Arc<ServerState>>, Path(id): Path<String>) -> Response {
+    let index = state.index.get();
+
+    let entry = match index.videos.get(&id) {
+        Some(entry) => entry,
+        None => {
+            return error_response(
+                StatusCode::NOT_FOUND,
+                format!("Video {id} isn't in the archive!"),
+            )
+        }
+    };
+
+    let metadata = match entry.files.get("meta") {
+        Some(relative) => tokio::fs::read_to_string(format!("{}/{relative}", state.root))
+            .await
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok()),
+        None => None,
+    };
+    Json(VideoResponse { entry, metadata }).into_response()
+}
+
+#[derive(Serialize)]
+struct ChannelResponse {
+    name: String,
+    videos: usize,
+}
+
+async fn list_channels(State(state): State<Arc<ServerState>>) -> Response {
+    let index = state.index.get();
+
+    let mut channels: BTreeMap<&str, usize> = BTreeMap::new();
+    for entry in index.videos.values() {
+        *channels.entry(&entry.channel).or_default() += 1;
+    }
+
+    let channels: Vec<ChannelResponse> = channels
+        .into_iter()
+        .map(|(name, videos)| ChannelResponse {
+            name: String::from(name),
+            videos,
+        })
+        .collect();
+    Json(channels).into_response()
+}
+
+async fn list_jobs(State(state): State<Arc<ServerState>>) -> Response {
+    Json(state.queue.list()).into_response()
+}
+
+#[derive(Deserialize)]
+struct JobRequest {
+    url: String,
+}
+
+async fn add_job(State(state): State<Arc<ServerState>>, Json(job): Json<JobRequest>) -> Response {
+    let check_result = check_url(&job.url);
+    if check_result.is_err() {
+        let error = check_result.err().unwrap();
+        return error_response(StatusCode::BAD_REQUEST, error);
+    }
+
+    let job = state.queue.add(job.url);
+    if state.sender.send(job.clone()).is_err() {
+        return error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            String::from("The archiver has stopped taking jobs!"),
+        );
+    }
+
+    (StatusCode::ACCEPTED, Json(job)).into_response()
+}
+
+// files the archiver keeps for itself, which aren't part of any video
+fn is_internal_file(path: &str) -> bool {
+    path.split('/').any(|component| {
+        component == INDEX_FILENAME
+            || component == FAILED_FILENAME
+            || component == HISTORY_FOLDER
+            || component.ends_with(".sha256")
+            || component.ends_with(".part")
+    })
+}
+
+async fn get_file(
+    State(state): State<Arc<ServerState>>,
+    Path(path): Path<String>,
+    mut request: Request,
+) -> Response {
+    if is_internal_file(&path) {
+        return error_response(
+            StatusCode::NOT_FOUND,
+            format!("{path} isn't a file of any video!"),
+        );
+    }
+
+    // the file server expects paths relative to the archive root, which it decodes itself
+    let relative = request.uri().path().trim_start_matches("/files");
+    match relative.parse::<Uri>() {
+        Ok(uri) => *request.uri_mut() = uri,
+        Err(error) => return error_response(StatusCode::BAD_REQUEST, error.to_string()),
+    }
+
+    let serve_result = ServeDir::new(&state.root).try_call(request).await;
+    if serve_result.is_err() {
+        let error = serve_result.err().unwrap();
+        return error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Couldn't read {path}! Error: {error}"),
+        );
+    }
+
+    serve_result.unwrap().map(Body::new)
+}
+
+/// Serves the archive until the server fails, which only happens if it can't start.
+pub async fn serve(params: ServeParameters) -> Result<(), String> {
+    let state = Arc::new(ServerState {
+        root: params.root,
+        index: params.index,
+        queue: params.queue,
+        sender: params.sender,
+    });
+    let router = Router::new()
+        .route("/api/videos", get(list_videos))
+        .route("/api/videos/:id", get(get_video))
+        .route("/api/channels", get(list_channels))
+        .route("/api/jobs", get(list_jobs).post(add_job))
+        .route("/files/*path", get(get_file))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(&params.address).await;
+    if listener.is_err() {
+        let error = listener.err().unwrap();
+        return Err(format!(
+            "Couldn't listen on {}! Error: {error}",
+            params.address
+        ));
+    }
+
+    let serve_result = axum::serve(listener.unwrap(), router).await;
+    if serve_result.is_err() {
+        let error = serve_result.err().unwrap();
+        return Err(format!("The server stopped! Error: {error}"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,42 @@
+/*
+    File used to stop long running commands cleanly when asked to with Ctrl+C or SIGTERM,
+    letting the video being archived finish first instead of leaving it half done.
+*/
+use crate::log::*;
+use tokio::sync::watch::{channel, Receiver};
+
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let terminate = signal(SignalKind::terminate());
+        if let Ok(mut terminate) = terminate {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Starts listening for shutdown signals, returning a receiver that changes to true once one arrives.
+/// A second signal stops straight away.
+pub fn listen_for_shutdown() -> Receiver<bool> {
+    let (sender, receiver) = channel(false);
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        failure(String::from(
+            "Stopping once the current video is archived. Send the signal again to stop straight away.",
+        ));
+        let _ = sender.send(true);
+
+        wait_for_signal().await;
+        failure(String::from("Stopping straight away!"));
+        std::process::exit(130);
+    });
+
+    receiver
+}
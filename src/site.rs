@@ -0,0 +1,505 @@
+/*
+    File used to render an archive into a static HTML site that can be browsed offline,
+    with a page for every channel and video and a search that runs in the browser.
+*/
+use crate::archive::*;
+use crate::file::*;
+use crate::log::*;
+use crate::nfo::escape_xml;
+use crate::youtube::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{self, create_dir_all},
+    path::{Component, Path, PathBuf},
+};
+
+const STYLE: &str =
+    "body { font-family: sans-serif; margin: 0 auto; max-width: 1100px; padding: 1em; }
+a { color: inherit; }
+.videos { display: grid; grid-template-columns: repeat(auto-fill, minmax(240px, 1fr)); gap: 1em; }
+.video img { width: 100%; aspect-ratio: 16 / 9; object-fit: cover; background: #ddd; }
+video { width: 100%; max-height: 70vh; background: #000; }
+.description { white-space: pre-wrap; }
+.tags span { background: #eee; border-radius: 4px; margin-right: 0.3em; padding: 0.1em 0.4em; }
+.comment { border-top: 1px solid #ddd; padding: 0.5em 0; }
+.unavailable { color: #b00; }
+#search { font-size: 1.1em; margin-bottom: 1em; padding: 0.4em; width: 100%; }
+";
+
+const SEARCH_SCRIPT: &str = "const input = document.getElementById('search');
+const results = document.getElementById('results');
+input.addEventListener('input', () => {
+    const terms = input.value.toLowerCase().split(/\\s+/).filter((term) => term.length > 0);
+    results.innerHTML = '';
+    if (terms.length === 0) {
+        return;
+    }
+
+    for (const video of SEARCH_INDEX) {
+        const text = (video.title + ' ' + video.channel + ' ' + video.tags.join(' ') + ' ' + video.description).toLowerCase();
+        if (terms.every((term) => text.includes(term))) {
+            const item = document.createElement('li');
+            const link = document.createElement('a');
+            link.href = 'videos/' + video.id + '.html';
+            link.textContent = video.title + ' (' + video.channel + ')';
+            item.appendChild(link);
+            results.appendChild(item);
+        }
+    }
+});
+";
+
+#[derive(Serialize)]
+struct SearchEntry {
+    id: String,
+    title: String,
+    channel: String,
+    description: String,
+    tags: Vec<String>,
+}
+
+// comments in the same format as yt-dlp writes them
+#[derive(Deserialize)]
+struct Comment {
+    #[serde(default)]
+    author: String,
+    #[serde(default)]
+    text: String,
+}
+
+struct SiteVideo<'a> {
+    entry: &'a ArchiveEntry,
+    metadata: Option<ArchivedMetadata>,
+    channel: String,
+    // paths relative to the video pages
+    thumbnail: Option<String>,
+    video: Option<String>,
+    captions: Vec<(String, String)>,
+    comments: Vec<Comment>,
+}
+
+/// Works out the path of `target` relative to the folder `from`, falling back to an absolute path
+/// when there is no relative path between them (like on different drives).
+fn get_relative_path(from: &Path, target: &Path) -> String {
+    let from = fs::canonicalize(from).unwrap_or(from.to_path_buf());
+    let target = fs::canonicalize(target).unwrap_or(target.to_path_buf());
+    let from_components: Vec<Component> = from.components().collect();
+    let target_components: Vec<Component> = target.components().collect();
+    if from_components.first() != target_components.first() {
+        return target.to_string_lossy().replace("\\", "/");
+    }
+
+    let common = from_components
+        .iter()
+        .zip(&target_components)
+        .take_while(|(from, target)| from == target)
+        .count();
+    let mut relative = PathBuf::new();
+    for _ in common..from_components.len() {
+        relative.push("..");
+    }
+
+    for component in &target_components[common..] {
+        relative.push(component);
+    }
+
+    relative.to_string_lossy().replace("\\", "/")
+}
+
+/// Escapes every part of a path for use in a link, keeping the `/` between them.
+fn escape_url(path: &str) -> String {
+    path.split("/")
+        .map(|part| {
+            part.bytes()
+                .map(|byte| match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                        String::from(byte as char)
+                    }
+                    _ => format!("%{byte:02X}"),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Names the page of every channel, numbering channels whose names would otherwise end up as the same page,
+/// including ones that only differ in case since some file systems don't tell those apart.
+fn get_channel_pages<'a>(channels: impl Iterator<Item = &'a str>) -> BTreeMap<&'a str, String> {
+    let mut pages: BTreeMap<&str, String> = BTreeMap::new();
+    let mut used: HashSet<String> = HashSet::new();
+    for channel in channels {
+        let name = sanitize_page_name(channel);
+        let mut page = name.clone();
+        let mut number = 1;
+        while !used.insert(page.to_lowercase()) {
+            number += 1;
+            page = format!("{name}_{number}");
+        }
+
+        pages.insert(channel, format!("channels/{page}.html"));
+    }
+
+    pages
+}
+
+fn sanitize_page_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|character| {
+            if character.is_alphanumeric() || character == '-' || character == '_' {
+                character
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        String::from("_")
+    } else {
+        name
+    }
+}
+
+fn create_page(title: &str, depth: usize, body: &str) -> String {
+    let root = "../".repeat(depth);
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{}</title>
+<link rel=\"stylesheet\" href=\"{root}style.css\">
+</head>
+<body>
+<nav><a href=\"{root}index.html\">All channels</a></nav>
+{body}
+</body>
+</html>
+",
+        escape_xml(title)
+    )
+}
+
+fn create_video_card(video: &SiteVideo<'_>, root: &str) -> String {
+    let title = match &video.metadata {
+        Some(metadata) => metadata.title.clone(),
+        None => video.entry.title.clone(),
+    };
+    let thumbnail = match &video.thumbnail {
+        Some(thumbnail) => format!(
+            "<img loading=\"lazy\" src=\"{root}{}\" alt=\"\">",
+            escape_xml(thumbnail)
+        ),
+        None => String::from("<img alt=\"\">"),
+    };
+
+    format!(
+        "<div class=\"video\"><a href=\"{root}videos/{}.html\">{thumbnail}<br>{}</a></div>\n",
+        escape_url(&sanitize_page_name(&video.entry.id)),
+        escape_xml(if title.is_empty() {
+            &video.entry.id
+        } else {
+            &title
+        })
+    )
+}
+
+fn create_video_page(video: &SiteVideo<'_>, channel_page: &str) -> String {
+    let entry = video.entry;
+    let title = match &video.metadata {
+        Some(metadata) if !metadata.title.is_empty() => metadata.title.clone(),
+        _ if !entry.title.is_empty() => entry.title.clone(),
+        _ => entry.id.clone(),
+    };
+
+    let mut body = format!("<h1>{}</h1>\n", escape_xml(&title));
+    body += &format!(
+        "<p><a href=\"../{}\">{}</a>",
+        escape_url(channel_page),
+        escape_xml(&video.channel)
+    );
+    if let Some(metadata) = &video.metadata {
+        body += &format!(" &middot; {}", escape_xml(&metadata.publish_date));
+    }
+
+    body += &format!(
+        " &middot; <a href=\"https://www.youtube.com/watch?v={}\">YouTube</a></p>\n",
+        escape_url(&entry.id)
+    );
+    if let Some(unavailable) = &entry.unavailable {
+        body += &format!(
+            "<p class=\"unavailable\">No longer on YouTube: {} since {}.</p>\n",
+            escape_xml(unavailable.reason.describe()),
+            escape_xml(&unavailable.first_seen)
+        );
+    }
+
+    match &video.video {
+        Some(source) => {
+            let poster = match &video.thumbnail {
+                Some(thumbnail) => format!(" poster=\"../{}\"", escape_xml(thumbnail)),
+                None => String::new(),
+            };
+            body += &format!(
+                "<video controls preload=\"metadata\"{poster} src=\"../{}\">\n",
+                escape_xml(source)
+            );
+            for (language, captions) in &video.captions {
+                body += &format!(
+                    "<track kind=\"captions\" srclang=\"{0}\" label=\"{0}\" src=\"../{1}\">\n",
+                    escape_xml(language),
+                    escape_xml(captions)
+                );
+            }
+            body += "</video>\n";
+        }
+        None => body += "<p>The video itself wasn't archived.</p>\n",
+    }
+
+    if let Some(metadata) = &video.metadata {
+        body += &format!(
+            "<p class=\"description\">{}</p>\n",
+            escape_xml(&metadata.description)
+        );
+        if !metadata.tags.is_empty() {
+            body += "<p class=\"tags\">";
+            for tag in &metadata.tags {
+                body += &format!("<span>{}</span>", escape_xml(tag));
+            }
+            body += "</p>\n";
+        }
+    }
+
+    if !video.comments.is_empty() {
+        body += &format!("<h2>{} comments</h2>\n", video.comments.len());
+        for comment in &video.comments {
+            body += &format!(
+                "<div class=\"comment\"><b>{}</b><p class=\"description\">{}</p></div>\n",
+                escape_xml(&comment.author),
+                escape_xml(&comment.text)
+            );
+        }
+    }
+
+    create_page(&title, 1, &body)
+}
+
+/// Finds caption files next to a video, which are named like `<video name>.<language>.vtt`.
+fn find_captions(root: &str, video_file: &str) -> Vec<(String, String)> {
+    let mut captions: Vec<(String, String)> = Vec::new();
+    let video_path = Path::new(video_file);
+    let (folder, stem) = match (video_path.parent(), video_path.file_stem()) {
+        (Some(folder), Some(stem)) => (folder, stem.to_string_lossy().to_string()),
+        _ => return captions,
+    };
+
+    let files = fs::read_dir(Path::new(root).join(folder));
+    if files.is_err() {
+        return captions;
+    }
+
+    for file in files.unwrap().flatten() {
+        let name = file.file_name().to_string_lossy().to_string();
+        let language = name
+            .strip_prefix(&format!("{stem}."))
+            .and_then(|rest| rest.strip_suffix(".vtt"));
+        if let Some(language) = language {
+            let relative = folder.join(&name).to_string_lossy().replace("\\", "/");
+            captions.push((String::from(language), relative));
+        }
+    }
+
+    captions.sort();
+    captions
+}
+
+fn load_video<'a>(root: &str, entry: &'a ArchiveEntry, prefix: &str) -> SiteVideo<'a> {
+    let paths = ArchivePaths::from_entry(root, entry);
+    let metadata = fs::read_to_string(paths.get_file("meta", "json"))
+        .ok()
+        .and_then(|contents| serde_json::from_str::<ArchivedMetadata>(&contents).ok());
+    let comments = fs::read_to_string(paths.get_file("comments", "json"))
+        .ok()
+        .and_then(|contents| serde_json::from_str::<Vec<Comment>>(&contents).ok())
+        .unwrap_or_default();
+    let channel = match &metadata {
+        Some(metadata) => metadata.creator.clone(),
+        None => entry.channel.clone(),
+    };
+    let thumbnail = THUMBNAIL_SIZES
+        .iter()
+        .rev()
+        .find_map(|size| entry.files.get(&format!("thumb_{size}")))
+        .map(|relative| format!("{prefix}{}", escape_url(relative)));
+    let video_file = entry.files.get("source_h264");
+    let captions = match video_file {
+        Some(video_file) => find_captions(root, video_file)
+            .into_iter()
+            .map(|(language, relative)| (language, format!("{prefix}{}", escape_url(&relative))))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    SiteVideo {
+        entry,
+        metadata,
+        channel: if channel.is_empty() {
+            String::from("Unknown channel")
+        } else {
+            channel
+        },
+        thumbnail,
+        video: video_file.map(|relative| format!("{prefix}{}", escape_url(relative))),
+        captions,
+        comments,
+    }
+}
+
+fn write_page(output: &str, page: &str, contents: &str) -> Result<(), String> {
+    let filename = format!("{output}/{page}");
+    if let Some(parent) = Path::new(&filename).parent() {
+        let create_result = create_dir_all(parent);
+        if create_result.is_err() {
+            let error = create_result.err().unwrap();
+            return Err(format!(
+                "Couldn't create folder {}! Error: {error}",
+                parent.display()
+            ));
+        }
+    }
+
+    write_atomic(&filename, contents.as_bytes())
+}
+
+/// Renders every video in the archive into a static site in `output`.
+pub fn render_site(root: &str, output: &str, index: &ArchiveIndex) -> Result<usize, String> {
+    let create_result = create_dir_all(output);
+    if create_result.is_err() {
+        let error = create_result.err().unwrap();
+        return Err(format!("Couldn't create folder {output}! Error: {error}"));
+    }
+
+    // pages are one folder deep, so links to archived files go up one more folder than the site itself
+    let prefix = match get_relative_path(Path::new(output), Path::new(root)).as_str() {
+        "" => String::new(),
+        relative => format!("{relative}/"),
+    };
+    let videos: Vec<SiteVideo> = index
+        .videos
+        .values()
+        .map(|entry| load_video(root, entry, &prefix))
+        .collect();
+
+    let mut channels: BTreeMap<&str, Vec<&SiteVideo>> = BTreeMap::new();
+    for video in &videos {
+        channels.entry(&video.channel).or_default().push(video);
+    }
+
+    let channel_pages = get_channel_pages(channels.keys().copied());
+    let mut search_index: Vec<SearchEntry> = Vec::new();
+    for video in &videos {
+        let write_result = write_page(
+            output,
+            &format!("videos/{}.html", sanitize_page_name(&video.entry.id)),
+            &create_video_page(video, &channel_pages[video.channel.as_str()]),
+        );
+        if write_result.is_err() {
+            return Err(write_result.err().unwrap());
+        }
+
+        search_index.push(SearchEntry {
+            id: escape_url(&sanitize_page_name(&video.entry.id)),
+            title: match &video.metadata {
+                Some(metadata) => metadata.title.clone(),
+                None => video.entry.title.clone(),
+            },
+            channel: video.channel.clone(),
+            description: video
+                .metadata
+                .as_ref()
+                .map(|metadata| metadata.description.clone())
+                .unwrap_or_default(),
+            tags: video
+                .metadata
+                .as_ref()
+                .map(|metadata| metadata.tags.clone())
+                .unwrap_or_default(),
+        });
+    }
+
+    let mut channel_list = String::new();
+    for (channel, channel_videos) in &channels {
+        let mut body = format!("<h1>{}</h1>\n<div class=\"videos\">\n", escape_xml(channel));
+        for video in channel_videos {
+            body += &create_video_card(video, "../");
+        }
+        body += "</div>\n";
+
+        let page = &channel_pages[channel];
+        let write_result = write_page(output, page, &create_page(channel, 1, &body));
+        if write_result.is_err() {
+            return Err(write_result.err().unwrap());
+        }
+
+        channel_list += &format!(
+            "<li><a href=\"{}\">{}</a> ({} videos)</li>\n",
+            escape_url(page),
+            escape_xml(channel),
+            channel_videos.len()
+        );
+    }
+
+    let index_body = format!(
+        "<h1>Archive</h1>
+<input id=\"search\" type=\"search\" placeholder=\"Search {} videos\">
+<ul id=\"results\"></ul>
+<h2>Channels</h2>
+<ul>
+{channel_list}</ul>
+<script src=\"search-index.js\"></script>
+<script src=\"search.js\"></script>
+",
+        videos.len()
+    );
+    // the index is a script rather than json, since browsers don't let local pages fetch files
+    let search_index_script = format!(
+        "const SEARCH_INDEX = {};\n",
+        serde_json::to_string(&search_index).unwrap()
+    );
+    for (page, contents) in [
+        ("index.html", create_page("Archive", 0, &index_body)),
+        ("style.css", String::from(STYLE)),
+        ("search.js", String::from(SEARCH_SCRIPT)),
+        ("search-index.js", search_index_script),
+    ] {
+        let write_result = write_page(output, page, &contents);
+        if write_result.is_err() {
+            return Err(write_result.err().unwrap());
+        }
+    }
+
+    success(format!(
+        "Rendered {} videos from {} channels into {output}.",
+        videos.len(),
+        channels.len()
+    ));
+    Ok(videos.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gives_every_channel_its_own_page() {
+        let pages = get_channel_pages(["A/B", "A?B", "a_b", "Other"].into_iter());
+
+        assert_eq!(pages["A/B"], "channels/A_B.html");
+        assert_eq!(pages["A?B"], "channels/A_B_2.html");
+        assert_eq!(pages["a_b"], "channels/a_b_3.html");
+        assert_eq!(pages["Other"], "channels/Other.html");
+    }
+}
@@ -0,0 +1,306 @@
+/*
+    File used to turn output templates like `{channel}/{upload_date:%Y}/{title} [{id}]` into safe paths.
+*/
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, FixedOffset,
+};
+
+// leaves room for the artifact name, extension and temporary file suffixes within the usual 255 byte limit
+const MAX_COMPONENT_LENGTH: usize = 200;
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+pub const ARTIFACT_MARKER: &str = "{artifact}";
+
+#[derive(Debug, Clone)]
+pub struct TemplateFields {
+    pub id: String,
+    pub title: String,
+    pub channel: String,
+    pub upload_date: Option<DateTime<FixedOffset>>,
+}
+
+impl TemplateFields {
+    pub fn from_id(id: String) -> TemplateFields {
+        TemplateFields {
+            id,
+            title: String::new(),
+            channel: String::new(),
+            upload_date: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Text(String),
+    Field(String, Option<String>),
+    Artifact,
+}
+
+#[derive(Debug, Clone)]
+pub struct Template {
+    components: Vec<Vec<Token>>,
+}
+
+impl Template {
+    fn parse(template: &str) -> Result<Template, String> {
+        let mut components: Vec<Vec<Token>> = Vec::new();
+        for component in template.split("/") {
+            if component.is_empty() {
+                continue;
+            }
+
+            let tokens = parse_component(component);
+            if tokens.is_err() {
+                let error = tokens.err().unwrap();
+                return Err(format!("Invalid template {template}! Error: {error}"));
+            }
+
+            components.push(tokens.unwrap());
+        }
+
+        Ok(Template { components })
+    }
+
+    pub fn parse_folder(template: &str) -> Result<Template, String> {
+        if template.contains(ARTIFACT_MARKER) {
+            return Err(format!(
+                "Invalid template {template}! Only file templates can contain {ARTIFACT_MARKER}."
+            ));
+        }
+
+        Template::parse(template)
+    }
+
+    pub fn parse_file(template: &str) -> Result<Template, String> {
+        if template.contains("/") || template.contains("\\") {
+            return Err(format!(
+                "Invalid file template {template}! File templates can't contain folders."
+            ));
+        }
+
+        Template::parse(template)
+    }
+
+    /// Whether rendering needs anything besides the video id, meaning the metadata has to be requested first.
+    pub fn needs_metadata(&self) -> bool {
+        self.components.iter().flatten().any(|token| match token {
+            Token::Field(name, _) => name != "id",
+            _ => false,
+        })
+    }
+
+    /// Whether the first folder of the template is named after the channel,
+    /// meaning it holds every video of that channel.
+    pub fn starts_with_channel(&self) -> bool {
+        match self.components.first() {
+            Some(component) => component.iter().any(|token| match token {
+                Token::Field(name, _) => name == "channel",
+                _ => false,
+            }),
+            None => false,
+        }
+    }
+
+    /// Renders every component of the template and joins them with `/`.
+    pub fn render(&self, fields: &TemplateFields) -> String {
+        let mut rendered: Vec<String> = Vec::new();
+        for component in &self.components {
+            rendered.push(render_component(component, fields));
+        }
+
+        rendered.join("/")
+    }
+}
+
+fn parse_component(component: &str) -> Result<Vec<Token>, String> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut text = String::new();
+    let mut characters = component.chars().peekable();
+    while let Some(character) = characters.next() {
+        match character {
+            '{' if characters.peek() == Some(&'{') => {
+                characters.next();
+                text.push('{');
+            }
+            '}' if characters.peek() == Some(&'}') => {
+                characters.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut field = String::new();
+                let mut closed = false;
+                for character in characters.by_ref() {
+                    if character == '}' {
+                        closed = true;
+                        break;
+                    }
+
+                    field.push(character);
+                }
+
+                if !closed {
+                    return Err(format!("The field {{{field} is never closed."));
+                }
+
+                if !text.is_empty() {
+                    tokens.push(Token::Text(text.clone()));
+                    text.clear();
+                }
+
+                let token = parse_field(&field);
+                if token.is_err() {
+                    return Err(token.err().unwrap());
+                }
+
+                tokens.push(token.unwrap());
+            }
+            '}' => return Err(String::from("Found a } without a matching {.")),
+            _ => text.push(character),
+        }
+    }
+
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+
+    Ok(tokens)
+}
+
+fn parse_field(field: &str) -> Result<Token, String> {
+    let (name, format) = match field.split_once(":") {
+        Some((name, format)) => (name, Some(String::from(format))),
+        None => (field, None),
+    };
+
+    match name {
+        "artifact" if format.is_none() => Ok(Token::Artifact),
+        "id" | "title" | "channel" if format.is_none() => {
+            Ok(Token::Field(String::from(name), None))
+        }
+        "upload_date" => {
+            if let Some(format) = &format {
+                if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                    return Err(format!("{format} is not a valid date format."));
+                }
+            }
+
+            Ok(Token::Field(String::from(name), format))
+        }
+        "artifact" | "id" | "title" | "channel" => {
+            Err(format!("The field {name} doesn't take a format."))
+        }
+        _ => Err(format!(
+            "Unknown field {name}, expected one of id, title, channel, upload_date or artifact."
+        )),
+    }
+}
+
+fn render_component(component: &[Token], fields: &TemplateFields) -> String {
+    let rendered = render_tokens(component, fields, None);
+    if rendered.len() <= MAX_COMPONENT_LENGTH {
+        return rendered;
+    }
+
+    // titles are the usual reason for long names, so they get shortened before anything else
+    let overflow = rendered.len() - MAX_COMPONENT_LENGTH;
+    let title_length = fields.title.len().saturating_sub(overflow);
+    let shortened = render_tokens(component, fields, Some(title_length));
+    if shortened.len() <= MAX_COMPONENT_LENGTH {
+        return shortened;
+    }
+
+    // the artifact marker has to stay whole, or every artifact of the video would get the same name
+    match shortened.split_once(ARTIFACT_MARKER) {
+        Some((before, after)) => {
+            let length = MAX_COMPONENT_LENGTH - ARTIFACT_MARKER.len();
+            let after = truncate(after, length);
+            let before = truncate(before, length - after.len());
+            format!("{}{ARTIFACT_MARKER}{after}", before.trim_end())
+        }
+        None => String::from(truncate(&shortened, MAX_COMPONENT_LENGTH).trim_end()),
+    }
+}
+
+fn render_tokens(
+    component: &[Token],
+    fields: &TemplateFields,
+    title_length: Option<usize>,
+) -> String {
+    let mut rendered = String::new();
+    for token in component {
+        match token {
+            Token::Text(text) => rendered += &sanitize(text),
+            Token::Artifact => rendered += ARTIFACT_MARKER,
+            Token::Field(name, format) => {
+                let value = match name.as_str() {
+                    "id" => fields.id.clone(),
+                    "title" => match title_length {
+                        Some(length) => String::from(truncate(&fields.title, length).trim_end()),
+                        None => fields.title.clone(),
+                    },
+                    "channel" => fields.channel.clone(),
+                    "upload_date" => match &fields.upload_date {
+                        Some(date) => date
+                            .format(format.as_deref().unwrap_or(DEFAULT_DATE_FORMAT))
+                            .to_string(),
+                        None => String::from("NA"),
+                    },
+                    _ => unreachable!("fields are checked while parsing"),
+                };
+                rendered += &sanitize(&value);
+            }
+        }
+    }
+
+    // names that are empty, or only dots, aren't usable as paths
+    let trimmed = rendered.trim().trim_end_matches(".");
+    if trimmed.is_empty() || trimmed.chars().all(|character| character == '.') {
+        return String::from("_");
+    }
+
+    String::from(trimmed)
+}
+
+/// Replaces characters that aren't allowed in file names on common filesystems.
+pub fn sanitize(input: &str) -> String {
+    input
+        .chars()
+        .map(|character| match character {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            character if character.is_control() => '_',
+            character => character,
+        })
+        .collect()
+}
+
+fn truncate(input: &str, length: usize) -> &str {
+    if input.len() <= length {
+        return input;
+    }
+
+    let mut end = length;
+    while !input.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    &input[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_artifact_marker_when_shortening_names() {
+        let template = Template::parse_file("{channel} - {title} - {artifact}").unwrap();
+        let fields = TemplateFields {
+            channel: "c".repeat(300),
+            ..TemplateFields::from_id(String::from("video00001"))
+        };
+
+        let rendered = template.render(&fields);
+        assert!(rendered.len() <= MAX_COMPONENT_LENGTH, "{rendered}");
+        assert!(rendered.ends_with(ARTIFACT_MARKER), "{rendered}");
+    }
+}
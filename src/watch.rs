@@ -0,0 +1,203 @@
+/*
+    File used to keep watching channels and playlists for new videos, archiving them as they're uploaded.
+    Feeds are checked most of the time since they're cheap, with every video listed now and then to catch anything they missed.
+*/
+use crate::archive::*;
+use crate::archiver::*;
+use crate::feed::*;
+use crate::log::*;
+use crate::report::*;
+use crate::youtube::*;
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tokio::sync::watch::Receiver;
+
+pub struct WatchRequest {
+    pub urls: Vec<String>,
+    // seconds between checks
+    pub interval: u64,
+    // seconds between listing every video instead of only checking feeds
+    pub full_sync_interval: u64,
+}
+
+impl Archiver {
+    /// Checks the channels and playlists to watch every `interval` seconds until `stopping` changes to true,
+    /// archiving any videos that aren't in the archive yet. Only their feeds are checked,
+    /// apart from listing every video once every `full_sync_interval` seconds.
+    pub async fn watch(&self, params: WatchRequest, mut stopping: Receiver<bool>) {
+        let urls = params.urls;
+        let mut channel_ids: HashMap<String, String> = HashMap::new();
+        let mut last_full_sync: Option<Instant> = None;
+        loop {
+            let full_sync = last_full_sync
+                .map(|time| time.elapsed() >= Duration::from_secs(params.full_sync_interval))
+                .unwrap_or(true);
+            let archived = if full_sync {
+                request(format!(
+                    "Listing every video of {} lists for a full sync",
+                    urls.len()
+                ));
+                last_full_sync = Some(Instant::now());
+                self.archive_new_videos(&urls, &stopping).await
+            } else {
+                request(format!(
+                    "Checking the feeds of {} lists for new videos",
+                    urls.len()
+                ));
+                self.archive_feed_videos(&urls, &mut channel_ids, &stopping)
+                    .await
+            };
+            if *stopping.borrow() {
+                success(format!(
+                    "Archived {archived} new videos before stopping, stopped watching."
+                ));
+                return;
+            }
+
+            success(format!(
+                "Archived {archived} new videos, checking again in {} seconds.",
+                params.interval
+            ));
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(params.interval)) => {}
+                _ = stopping.changed() => {
+                    success(String::from("Stopped watching."));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Archives videos one at a time, returning false if asked to stop part way through.
+    async fn archive_watched_videos(
+        &self,
+        videos: Vec<String>,
+        stopping: &Receiver<bool>,
+        archived: &mut usize,
+    ) -> bool {
+        for video in videos {
+            let result = self.archive_list_video(&video).await;
+            if matches!(
+                result.outcome,
+                VideoOutcome::Archived | VideoOutcome::Partial
+            ) {
+                *archived += 1;
+            }
+            // the video that was being archived is finished, so this is a safe place to stop
+            if *stopping.borrow() {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn load_watched_index(&self) -> Option<ArchiveIndex> {
+        let index_result = ArchiveIndex::load_all(self.dir());
+        if index_result.is_err() {
+            let error = index_result.err().unwrap();
+            failure(format!(
+                "Couldn't check the existing archive! Error: {error}"
+            ));
+            return None;
+        }
+
+        index_result.ok()
+    }
+
+    /// Archives the videos of every list that aren't in the archive yet, returning how many were archived.
+    /// Every page is checked, since this is what catches older videos that the feeds missed.
+    async fn archive_new_videos(&self, urls: &[String], stopping: &Receiver<bool>) -> usize {
+        let index = match self.load_watched_index() {
+            Some(index) => index,
+            None => return 0,
+        };
+
+        let mut ids: HashSet<String> = HashSet::new();
+        let mut archived = 0;
+        for url in urls {
+            let mut pages = self.request_video_pages(url);
+            while let Some(page) = pages.next().await {
+                if page.is_err() {
+                    let error = page.err().unwrap();
+                    failure(format!(
+                        "Encountered an error while checking {url} for new videos! Error: {error}"
+                    ));
+                    break;
+                }
+
+                let new_videos = take_new_videos(page.unwrap(), &index, &mut ids);
+                if !self
+                    .archive_watched_videos(new_videos, stopping, &mut archived)
+                    .await
+                {
+                    return archived;
+                }
+            }
+        }
+
+        archived
+    }
+
+    /// Archives the videos in the feed of every list that aren't in the archive yet, returning how many were archived.
+    async fn archive_feed_videos(
+        &self,
+        urls: &[String],
+        channel_ids: &mut HashMap<String, String>,
+        stopping: &Receiver<bool>,
+    ) -> usize {
+        let index = match self.load_watched_index() {
+            Some(index) => index,
+            None => return 0,
+        };
+
+        let mut ids: HashSet<String> = HashSet::new();
+        let mut archived = 0;
+        for url in urls {
+            let videos = request_feed(FeedRequest {
+                url,
+                api: self.youtube_api().clone(),
+                filters: self.filters(),
+                channel_ids,
+            })
+            .await;
+            if videos.is_err() {
+                let error = videos.err().unwrap();
+                failure(format!(
+                    "Encountered an error while checking the feed of {url} for new videos! Error: {error}"
+                ));
+                continue;
+            }
+
+            let new_videos = take_new_videos(videos.unwrap(), &index, &mut ids);
+            if !self
+                .archive_watched_videos(new_videos, stopping, &mut archived)
+                .await
+            {
+                return archived;
+            }
+        }
+
+        archived
+    }
+}
+
+/// Keeps only the videos that haven't got any files in the archive and haven't been seen yet this check.
+fn take_new_videos(
+    videos: Vec<String>,
+    index: &ArchiveIndex,
+    ids: &mut HashSet<String>,
+) -> Vec<String> {
+    videos
+        .into_iter()
+        .filter(|video| {
+            let id = get_id_from_url(video).unwrap_or_else(|_| video.clone());
+            let archived = index
+                .videos
+                .get(&id)
+                .is_some_and(|entry| !entry.files.is_empty());
+            !archived && ids.insert(id)
+        })
+        .collect()
+}
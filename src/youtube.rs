@@ -1,172 +1,380 @@
+use crate::archive::*;
+use crate::file::*;
+use crate::filter::*;
+use crate::history::*;
 use crate::http::*;
+use crate::input::*;
 use crate::log::*;
-use async_recursion::async_recursion;
-use reqwest::Client;
+use crate::template::*;
+use crate::ytdlp::*;
+use async_stream::stream;
+use chrono::DateTime;
+use futures::{Future, Stream};
 use serde::{Deserialize, Serialize};
-use std::{fs::File, io::Write};
+use std::{collections::BTreeMap, fmt, fs, time::Duration};
+use tokio::time::sleep;
+
+#[derive(Clone)]
+pub struct YouTubeApi {
+    pub url: String,
+    pub key: Option<String>,
+    // shared by every request, including the ones that don't go to the api like thumbnails and feeds
+    pub client: HttpClient,
+}
 
-#[derive(Debug)]
-pub struct MetadataParameters<'a> {
-    pub url: &'a String,
-    pub dir: &'a String,
+impl YouTubeApi {
+    // instances of the youtube operational api serve keyless requests under /noKey,
+    // while the official api (or an operational api instance given a key) needs the key= parameter
+    fn get_endpoint(&self, path: String) -> String {
+        match &self.key {
+            Some(key) => format!("{}/{path}&key={key}", self.url),
+            None => format!("{}/noKey/{path}", self.url),
+        }
+    }
+}
+
+impl fmt::Debug for YouTubeApi {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("YouTubeApi")
+            .field("url", &self.url)
+            .field("key", &self.key.as_ref().map(|_| "<hidden>"))
+            .finish_non_exhaustive()
+    }
+}
+
+pub enum VideoAvailability {
+    Available(Box<ItemResponse>),
+    Unavailable(UnavailableReason),
 }
 
-pub async fn request_metadata(params: MetadataParameters<'_>, api: String) {
-    let client = Client::new();
-    let id = get_id_from_url(params.url);
-    let meta_result =
-        download_metadata(format!("{api}/noKey/videos?part=snippet&id={id}"), &client).await;
+/// Requests the snippet of a single video, which is needed both for archiving metadata and for output templates.
+/// Videos that have been deleted, made private or blocked come back without any snippet, in which case the reason is worked out instead.
+pub async fn request_video_metadata(
+    url: &str,
+    api: &YouTubeApi,
+) -> Result<VideoAvailability, String> {
+    let client = &api.client;
+    let id = get_id_from_url(url);
+    if id.is_err() {
+        return Err(id.err().unwrap());
+    }
+
+    let id = id.unwrap();
+    let meta_result = download_metadata(
+        api.get_endpoint(format!(
+            "videos?part=snippet,contentDetails,statistics,liveStreamingDetails&id={id}"
+        )),
+        client,
+    )
+    .await;
     if meta_result.is_err() {
-        let error = meta_result.err().unwrap();
-        failure(error);
-        return;
+        return Err(meta_result.err().unwrap());
     }
 
     let meta = meta_result.unwrap();
-    assert!(
-        meta.items.len() > 0,
-        "YouTube API Response had no metadata items! Maybe try again later?"
-    );
+    if meta.items.is_empty() {
+        let reason = request_unavailable_reason(&id, client).await;
+        if reason.is_err() {
+            return Err(reason.err().unwrap());
+        }
 
-    let write_result = write_metadata(&meta.items[0], params.dir);
-    if write_result.is_err() {
-        let error = write_result.err().unwrap();
-        failure(error);
-        return;
+        return Ok(VideoAvailability::Unavailable(reason.unwrap()));
     }
 
-    let thumbnails = &meta.items[0].snippet.thumbnails;
-    if thumbnails.default.is_some() {
-        request_thumbnail(ThumbnailParameters {
-            url: &thumbnails.default.as_ref().unwrap().url,
-            filename: format!("{}/thumb_default.jpg", params.dir),
-            client: &client,
-        })
-        .await;
+    Ok(VideoAvailability::Available(Box::new(
+        meta.items.into_iter().next().unwrap(),
+    )))
+}
+
+/// Works out why a video isn't available through the api, using youtube's oembed endpoint
+/// which still answers for private and region blocked videos.
+async fn request_unavailable_reason(
+    id: &str,
+    client: &HttpClient,
+) -> Result<UnavailableReason, String> {
+    let result = get_request(
+        GetRequest {
+            url: format!(
+                "https://www.youtube.com/oembed?url=https://www.youtube.com/watch?v={id}&format=json"
+            ),
+            accept: Some(String::from("application/json")),
+        },
+        client,
+    )
+    .await;
+    if result.is_err() {
+        let error = result.err().unwrap();
+        return Err(format!(
+            "Couldn't check why video {id} is unavailable! Error: {error}"
+        ));
     }
 
-    if thumbnails.medium.is_some() {
-        request_thumbnail(ThumbnailParameters {
-            url: &thumbnails.medium.as_ref().unwrap().url,
-            filename: format!("{}/thumb_medium.jpg", params.dir),
-            client: &client,
-        })
+    // the api would have listed the video if it could be watched from wherever the api is hosted
+    Ok(match result.unwrap().status().as_u16() {
+        200 => UnavailableReason::Blocked,
+        401 | 403 => UnavailableReason::Private,
+        400 | 404 => UnavailableReason::Deleted,
+        _ => UnavailableReason::Unknown,
+    })
+}
+
+#[derive(Deserialize)]
+struct VideoIdListResponse {
+    items: Vec<VideoIdResponse>,
+}
+
+#[derive(Deserialize)]
+struct VideoIdResponse {
+    id: String,
+}
+
+/// Checks which videos are still available, 50 at a time, returning why each unavailable video isn't.
+pub async fn request_unavailable_videos(
+    ids: &[String],
+    api: &YouTubeApi,
+) -> Result<BTreeMap<String, UnavailableReason>, String> {
+    let client = &api.client;
+    let mut unavailable: BTreeMap<String, UnavailableReason> = BTreeMap::new();
+    for chunk in ids.chunks(50) {
+        let result = get_request(
+            GetRequest {
+                url: api.get_endpoint(format!("videos?part=id&id={}", chunk.join(","))),
+                accept: Some(String::from("application/json")),
+            },
+            client,
+        )
         .await;
+        if result.is_err() {
+            let error = result.err().unwrap();
+            return Err(format!(
+                "There was an error checking which videos are available! Error: {error}"
+            ));
+        }
+
+        let list_result = result.unwrap().json::<VideoIdListResponse>().await;
+        if list_result.is_err() {
+            let error = list_result.err().unwrap();
+            return Err(format!(
+                "There was an error parsing the available videos! Error: {error}"
+            ));
+        }
+
+        let available: Vec<String> = list_result
+            .unwrap()
+            .items
+            .into_iter()
+            .map(|item| item.id)
+            .collect();
+        for id in chunk {
+            if available.contains(id) {
+                continue;
+            }
+
+            let reason = request_unavailable_reason(id, client).await;
+            if reason.is_err() {
+                return Err(reason.err().unwrap());
+            }
+
+            unavailable.insert(id.clone(), reason.unwrap());
+        }
     }
 
-    if thumbnails.high.is_some() {
-        request_thumbnail(ThumbnailParameters {
-            url: &thumbnails.high.as_ref().unwrap().url,
-            filename: format!("{}/thumb_high.jpg", params.dir),
-            client: &client,
-        })
-        .await;
+    Ok(unavailable)
+}
+
+#[derive(Debug)]
+pub struct MetadataParameters<'a> {
+    pub item: &'a ItemResponse,
+    pub paths: &'a ArchivePaths,
+    pub format: &'a MetadataFormat,
+    pub client: &'a HttpClient,
+}
+
+/// Writes the metadata and thumbnails of a video, returning the artifacts that were written successfully.
+pub async fn request_metadata(params: MetadataParameters<'_>) -> Vec<String> {
+    let timestamp = get_timestamp();
+    let mut written: Vec<String> = Vec::new();
+    let mut changes: Vec<String> = Vec::new();
+    // every artifact is written on its own, so one failing doesn't stop the others
+    if params.format.writes_archiver() {
+        match write_metadata(params.item, params.paths, &timestamp) {
+            Ok(metadata_changes) => {
+                changes = metadata_changes;
+                written.push(String::from("meta"));
+            }
+            Err(error) => failure(error),
+        }
     }
 
-    if thumbnails.standard.is_some() {
-        request_thumbnail(ThumbnailParameters {
-            url: &thumbnails.standard.as_ref().unwrap().url,
-            filename: format!("{}/thumb_standard.jpg", params.dir),
-            client: &client,
-        })
-        .await;
+    if params.format.writes_info_json() {
+        match write_info_json(params.item, params.paths) {
+            Ok(()) => written.push(String::from(INFO_JSON_ARTIFACT)),
+            Err(error) => failure(error),
+        }
     }
 
-    if thumbnails.maxres.is_some() {
-        request_thumbnail(ThumbnailParameters {
-            url: &thumbnails.maxres.as_ref().unwrap().url,
-            filename: format!("{}/thumb_maxres.jpg", params.dir),
-            client: &client,
-        })
-        .await;
+    for size in THUMBNAIL_SIZES {
+        if let Some(thumbnail) = params.item.snippet.thumbnails.get(size) {
+            let artifact = format!("thumb_{size}");
+            let changed = request_thumbnail(ThumbnailParameters {
+                url: &thumbnail.url,
+                filename: params.paths.get_file(&artifact, "jpg"),
+                history_filename: params
+                    .paths
+                    .get_history_file(&artifact, "jpg", Some(&timestamp)),
+                paths: params.paths,
+                client: params.client,
+            })
+            .await;
+            if let Some(changed) = changed {
+                written.push(artifact);
+                if changed {
+                    changes.push(format!("thumbnail {size} changed"));
+                }
+            }
+        }
     }
-}
 
-#[derive(Serialize)]
-struct ArchivedMetadata {
-    id: String,
-    title: String,
-    description: String,
-    creator: String,
-    publish_date: String,
-    tags: Vec<String>,
+    if !changes.is_empty() {
+        success(format!(
+            "Video {} changed since it was last archived: {}",
+            params.item.id,
+            changes.join(", ")
+        ));
+        let log_result = log_changes(params.paths, &timestamp, &changes);
+        if log_result.is_err() {
+            let error = log_result.err().unwrap();
+            failure(error);
+        }
+    }
+
+    written
 }
 
-fn write_metadata(input: &ItemResponse, dir: &String) -> Result<(), String> {
-    let mut tags: Vec<String> = Vec::new();
-    if input.snippet.tags.is_some() {
-        tags = input.snippet.tags.as_ref().unwrap().clone();
+impl ItemResponse {
+    pub fn get_template_fields(&self) -> TemplateFields {
+        TemplateFields {
+            id: self.id.clone(),
+            title: self.snippet.title.clone(),
+            channel: self.snippet.channelTitle.clone(),
+            upload_date: DateTime::parse_from_rfc3339(&self.snippet.publishedAt).ok(),
+        }
     }
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub struct ArchivedMetadata {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub creator: String,
+    pub publish_date: String,
+    pub tags: Vec<String>,
+}
+
+/// Writes the metadata of a video, keeping the previous version in its history if it changed.
+/// Returns what changed, which is empty for newly archived videos.
+fn write_metadata(
+    input: &ItemResponse,
+    paths: &ArchivePaths,
+    timestamp: &str,
+) -> Result<Vec<String>, String> {
+    let tags = input.snippet.tags.clone().unwrap_or_default();
 
     let output_data = ArchivedMetadata {
         title: input.snippet.title.clone(),
         description: input.snippet.description.clone(),
         creator: input.snippet.channelTitle.clone(),
         publish_date: input.snippet.publishedAt.clone(),
-        tags: tags,
+        tags,
         id: input.id.clone(),
     };
-    let output_filename = format!("{dir}/meta.json");
-    let mut output_file = File::create(&output_filename).unwrap();
+    let output_filename = paths.get_file("meta", "json");
     let output_contents = serde_json::to_string_pretty(&output_data).unwrap();
 
-    let write_result = output_file.write_all(output_contents.as_bytes());
-    if !write_result.is_ok() {
-        return Err(format!(
-            "Couldn't write to file {}. Error: {:?}",
-            &output_filename,
-            write_result.err()
-        ));
+    let mut changes: Vec<String> = Vec::new();
+    if let Ok(previous_contents) = fs::read_to_string(&output_filename) {
+        if let Ok(previous) = serde_json::from_str::<ArchivedMetadata>(&previous_contents) {
+            changes = describe_changes(&previous, &output_data);
+        }
+
+        if !changes.is_empty() {
+            let snapshot_result = write_history_file(
+                paths,
+                &paths.get_history_file("meta", "json", Some(timestamp)),
+                previous_contents.as_bytes(),
+            );
+            if snapshot_result.is_err() {
+                return Err(snapshot_result.err().unwrap());
+            }
+        }
     }
 
-    let flush_result = output_file.flush();
-    if !flush_result.is_ok() {
-        return Err(format!(
-            "Couldn't flush file {}. Error: {:?}",
-            &output_filename,
-            flush_result.err().unwrap()
-        ));
+    let write_result = write_atomic(&output_filename, output_contents.as_bytes());
+    if write_result.is_err() {
+        return Err(write_result.err().unwrap());
     }
 
     success(format!(
         "Wrote to requested file {} successfully!",
         &output_filename
     ));
-    Ok(())
+    Ok(changes)
 }
 
 #[allow(non_snake_case)] // needed for youtube api
 #[derive(Debug, Deserialize)]
-struct ThumbnailResponse {
-    url: String,
+pub struct ThumbnailResponse {
+    pub url: String,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
 }
 
 #[allow(non_snake_case)] // needed for youtube api
 #[derive(Debug, Deserialize)]
-struct ThumbnailsResponse {
-    default: Option<ThumbnailResponse>,
-    medium: Option<ThumbnailResponse>,
-    high: Option<ThumbnailResponse>,
-    standard: Option<ThumbnailResponse>,
-    maxres: Option<ThumbnailResponse>,
+pub struct ThumbnailsResponse {
+    pub default: Option<ThumbnailResponse>,
+    pub medium: Option<ThumbnailResponse>,
+    pub high: Option<ThumbnailResponse>,
+    pub standard: Option<ThumbnailResponse>,
+    pub maxres: Option<ThumbnailResponse>,
+}
+
+impl ThumbnailsResponse {
+    pub fn get(&self, size: &str) -> &Option<ThumbnailResponse> {
+        match size {
+            "default" => &self.default,
+            "medium" => &self.medium,
+            "high" => &self.high,
+            "standard" => &self.standard,
+            "maxres" => &self.maxres,
+            _ => &None,
+        }
+    }
 }
 
 #[allow(non_snake_case)] // needed for youtube api
 #[derive(Debug, Deserialize)]
-struct SnippetResponse {
-    publishedAt: String,
-    title: String,
-    description: String,
-    thumbnails: ThumbnailsResponse,
-    channelTitle: String,
-    tags: Option<Vec<String>>,
+pub struct SnippetResponse {
+    pub publishedAt: String,
+    pub title: String,
+    pub description: String,
+    pub thumbnails: ThumbnailsResponse,
+    pub channelTitle: String,
+    pub channelId: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub liveBroadcastContent: Option<String>,
 }
 
 #[allow(non_snake_case)] // needed for youtube api
 #[derive(Debug, Deserialize)]
-struct ItemResponse {
-    id: String,
-    snippet: SnippetResponse,
+pub struct ItemResponse {
+    pub id: String,
+    pub snippet: SnippetResponse,
+    pub contentDetails: Option<ContentDetailsResponse>,
+    pub statistics: Option<StatisticsResponse>,
+    pub liveStreamingDetails: Option<LiveStreamingDetailsResponse>,
 }
 
 #[allow(non_snake_case)] // needed for youtube api
@@ -175,11 +383,14 @@ struct YouTubeResponse {
     items: Vec<ItemResponse>,
 }
 
-async fn download_metadata(url: String, client: &Client) -> Result<YouTubeResponse, String> {
-    request(format!("Requesting metadata at this url: {}", &url));
+async fn download_metadata(url: String, client: &HttpClient) -> Result<YouTubeResponse, String> {
+    request(format!(
+        "Requesting metadata at this url: {}",
+        redact_url(&url)
+    ));
     let result = get_request(
         GetRequest {
-            url: url,
+            url,
             accept: Some(String::from("application/json")),
         },
         client,
@@ -191,9 +402,15 @@ async fn download_metadata(url: String, client: &Client) -> Result<YouTubeRespon
         return Err(format!("Error while getting metadata! Error: {error}"));
     }
 
-    let contents = result.unwrap().text().await.unwrap();
+    let contents = result.unwrap().text().await;
+    if contents.is_err() {
+        let error = contents.err().unwrap();
+        return Err(format!("Error while reading metadata! Error: {error}"));
+    }
+
+    let contents = contents.unwrap();
     let bytes = contents.as_bytes();
-    let response = serde_json::from_slice(&bytes);
+    let response = serde_json::from_slice(bytes);
     if response.is_err() {
         let error = response.err().unwrap();
         return Err(format!(
@@ -208,23 +425,30 @@ async fn download_metadata(url: String, client: &Client) -> Result<YouTubeRespon
 struct ThumbnailParameters<'a> {
     url: &'a String,
     filename: String,
-    client: &'a Client,
+    history_filename: String,
+    paths: &'a ArchivePaths,
+    client: &'a HttpClient,
 }
 
-async fn request_thumbnail(params: ThumbnailParameters<'_>) {
+/// Downloads a thumbnail, returning whether it changed since it was last archived,
+/// or nothing if it couldn't be downloaded.
+async fn request_thumbnail(params: ThumbnailParameters<'_>) -> Option<bool> {
     request(format!(
-        "Downloading thumbnail with these parameters: {params:?}"
+        "Downloading thumbnail from {} to {}",
+        params.url, params.filename
     ));
     let result = download_thumbnail(params).await;
 
     if result.is_err() {
         let error = result.err().unwrap();
         failure(format!("Error while downloading thumbnail! Error: {error}"));
-        return;
+        return None;
     }
+
+    Some(result.unwrap())
 }
 
-async fn download_thumbnail(params: ThumbnailParameters<'_>) -> Result<(), String> {
+async fn download_thumbnail(params: ThumbnailParameters<'_>) -> Result<bool, String> {
     let result = get_request(
         GetRequest {
             url: params.url.to_owned(),
@@ -241,64 +465,99 @@ async fn download_thumbnail(params: ThumbnailParameters<'_>) -> Result<(), Strin
         ));
     }
 
+    // an error page would otherwise be saved as the thumbnail
     let response = result.unwrap();
-    let contents = response.bytes().await.unwrap();
-    let mut output_file = File::create(params.filename.clone()).unwrap();
-
-    let write_result = output_file.write_all(&contents);
-    if !write_result.is_ok() {
+    if !response.status().is_success() {
         return Err(format!(
-            "Couldn't write to file {}. Error: {:?}",
-            params.filename,
-            write_result.err()
+            "Failed to download {}! It responded with status {}.",
+            params.url,
+            response.status()
         ));
     }
 
-    let flush_result = output_file.flush();
-    if !flush_result.is_ok() {
+    let contents = response.bytes().await;
+    if contents.is_err() {
         return Err(format!(
-            "Couldn't flush file {}. Error: {:?}",
-            params.filename,
-            flush_result.err().unwrap()
+            "Failed to download {}! Error: {:?}",
+            params.url,
+            contents.err().unwrap()
         ));
     }
 
+    let contents = contents.unwrap();
+    let mut changed = false;
+    if let Ok(previous_contents) = fs::read(&params.filename) {
+        if previous_contents != contents {
+            let snapshot_result =
+                write_history_file(params.paths, &params.history_filename, &previous_contents);
+            if snapshot_result.is_err() {
+                return Err(snapshot_result.err().unwrap());
+            }
+
+            changed = true;
+        }
+    }
+
+    let write_result = write_atomic(&params.filename, &contents);
+    if write_result.is_err() {
+        return Err(write_result.err().unwrap());
+    }
+
     success(format!(
         "Wrote to requested file {} successfully!",
         params.filename
     ));
-    Ok(())
+    Ok(changed)
 }
 
-pub fn get_id_from_url(input_url: &String) -> String {
-    assert!(
-        input_url.find("youtu").is_some(),
-        "Make sure to provide a valid YouTube URL!"
-    );
-    let mut clean_url = input_url.split_at(input_url.find("youtu").unwrap()).1;
+pub fn get_id_from_url(input_url: &str) -> Result<String, String> {
+    let start = input_url.find("youtu");
+    if start.is_none() {
+        return Err(format!("{input_url} isn't a YouTube URL!"));
+    }
+
+    let mut clean_url = input_url.split_at(start.unwrap()).1;
 
     // removes ?t= stuff from urls like this: https://youtu.be/dQw4w9WgXcQ?t=11
-    let time_marker = clean_url.find("?t");
-    if time_marker.is_some() {
-        clean_url = clean_url.split_at(time_marker.unwrap()).0;
+    if let Some(time_marker) = clean_url.find("?t") {
+        clean_url = clean_url.split_at(time_marker).0;
     }
 
-    if clean_url.starts_with("youtube.com/watch?v=") {
+    let id = if clean_url.starts_with("youtube.com/watch?v=") {
+        // removes & from urls like this: https://youtube.com/watch?v=dQw4w9WgXcQ&list=blahblahblah
+        if let Some(ampersand) = clean_url.find("&") {
+            clean_url = clean_url.split_at(ampersand).0;
+        }
+
         // youtube.com/watch?v=id
-        String::from(clean_url.split_at(clean_url.find("=").unwrap() + 1).1)
+        clean_url.split_once("=").map(|(_, id)| id)
     } else {
         // removes ? from urls like this: https://youtu.be/dQw4w9WgXcQ?si=blahblahblah
         // from the share button for example
-        let question = clean_url.find("?");
-        if question.is_some() {
-            clean_url = clean_url.split_at(question.unwrap()).0;
+        if let Some(question) = clean_url.find("?") {
+            clean_url = clean_url.split_at(question).0;
         }
 
         // youtu.be/id
-        String::from(clean_url.split_at(clean_url.find("/").unwrap() + 1).1)
+        clean_url.split_once("/").map(|(_, id)| id)
+    };
+
+    match id {
+        Some(id) if !id.is_empty() && !id.contains("/") => Ok(String::from(id)),
+        _ => Err(format!("Couldn't find a video id in {input_url}!")),
     }
 }
 
+/// Checks that a url is a video, playlist or channel the archiver can read, without requesting anything.
+pub fn check_url(url: &str) -> Result<(), String> {
+    let result = match get_input_kind(url) {
+        InputKind::Video => get_id_from_url(url),
+        InputKind::Playlist => get_playlist_id_from_url(url),
+        InputKind::Channel => get_channel_handle_from_url(url),
+    };
+    result.map(|_| ())
+}
+
 #[allow(non_snake_case)] // needed for youtube api
 #[derive(Deserialize)]
 struct ChannelListResponse {
@@ -313,33 +572,32 @@ struct ChannelResponse {
 
 pub struct ChannelRequest<'a> {
     pub url: &'a String,
-    pub api: String,
-    pub include_streams_and_premieres: bool,
+    pub api: YouTubeApi,
+    pub filters: &'a VideoFilters,
 }
 
-pub async fn request_channel(params: ChannelRequest<'_>) -> Result<Vec<String>, String> {
-    let client = Client::new();
-    let channel_handle = get_channel_handle_from_url(params.url);
+pub(crate) async fn request_channel_id(
+    channel_handle: &String,
+    api: &YouTubeApi,
+    client: &HttpClient,
+) -> Result<String, String> {
     request(format!(
         "Requesting channel ID from handle {}!",
-        &channel_handle
+        channel_handle
     ));
 
-    let id_url = format!(
-        "{}/noKey/channels?part=id&forHandle=@{}",
-        params.api, &channel_handle
-    );
+    let id_url = api.get_endpoint(format!("channels?part=id&forHandle=@{}", channel_handle));
     let result = get_request(
         GetRequest {
             url: id_url,
             accept: Some(String::from("application/json")),
         },
-        &client,
+        client,
     )
     .await;
     if result.is_err() {
         let error = result.err().unwrap();
-        return Err(format!("There was an error requesting the channel ID from the channel handle {}! Error: {error}", &channel_handle));
+        return Err(format!("There was an error requesting the channel ID from the channel handle {}! Error: {error}", channel_handle));
     }
 
     let list_result = result.unwrap().json::<ChannelListResponse>().await;
@@ -349,42 +607,296 @@ pub async fn request_channel(params: ChannelRequest<'_>) -> Result<Vec<String>,
     }
 
     let list_response = list_result.unwrap();
-    assert!(
-        list_response.items.len() > 0,
-        "The specified channel handle has no associated channel!"
-    );
+    match list_response.items.first() {
+        Some(channel) => Ok(channel.id.clone()),
+        None => Err(format!(
+            "The channel handle @{channel_handle} has no associated channel!"
+        )),
+    }
+}
 
-    let channel_id = list_response.items.get(0).unwrap().id.clone();
-    request(format!(
-        "Requesting all videos from channel ID {}",
-        &channel_id
-    ));
-    let videos_request: Result<Vec<String>, String> = request_videos(VideosRequestParameters {
-        channel_id: channel_id,
-        api: params.api.clone(),
-        next_page: None,
-        previous_videos: None,
-        include_streams_and_premieres: params.include_streams_and_premieres,
-        client: client,
+/// Lists every video on a channel one page at a time, so videos can be archived while later pages are still coming in.
+/// Each page is tried a few times before giving up, and an error ends the stream.
+pub fn request_channel(
+    params: ChannelRequest<'_>,
+) -> impl Stream<Item = Result<Vec<String>, String>> + '_ {
+    stream! {
+        let client = params.api.client.clone();
+        let channel_handle = get_channel_handle_from_url(params.url);
+        if channel_handle.is_err() {
+            yield Err(channel_handle.err().unwrap());
+            return;
+        }
+
+        let channel_handle = channel_handle.unwrap();
+        let channel_id_result = request_channel_id(&channel_handle, &params.api, &client).await;
+        if channel_id_result.is_err() {
+            let error = channel_id_result.err().unwrap();
+            yield Err(error);
+            return;
+        }
+
+        let channel_id = channel_id_result.unwrap();
+        request(format!(
+            "Requesting all videos from channel ID {}",
+            &channel_id
+        ));
+
+        let mut next_page: Option<String> = None;
+        loop {
+            let page_result = retry_page(|| {
+                request_filtered_page(
+                    request_search_page(SearchPageParameters {
+                        channel_id: &channel_id,
+                        api: &params.api,
+                        next_page: &next_page,
+                        filters: params.filters,
+                        client: &client,
+                    }),
+                    PageFilterParameters {
+                        api: &params.api,
+                        filters: params.filters,
+                        client: &client,
+                    },
+                )
+            })
+            .await;
+            if page_result.is_err() {
+                let error = page_result.err().unwrap();
+                yield Err(format!(
+                    "There was an error getting all videos on the specified channel! Error: {error}"
+                ));
+                return;
+            }
+
+            let page = page_result.unwrap();
+            yield Ok(page.ids);
+
+            next_page = page.next_page;
+            if next_page.is_none() {
+                break;
+            }
+        }
+    }
+}
+
+pub fn get_channel_handle_from_url(url: &str) -> Result<String, String> {
+    let handle = url.split_once("@").map(|(_, handle)| handle);
+    match handle {
+        // removes anything after the handle from urls like this: https://youtube.com/@handle/videos
+        Some(handle) => match handle.split(['/', '?']).next() {
+            Some(handle) if !handle.is_empty() => Ok(String::from(handle)),
+            _ => Err(format!("Couldn't find a channel handle in {url}!")),
+        },
+        None => Err(format!("{url} isn't a YouTube channel URL!")),
+    }
+}
+
+pub fn get_playlist_id_from_url(url: &str) -> Result<String, String> {
+    let playlist_id = url.split_once("list=").map(|(_, id)| id);
+    if playlist_id.is_none() {
+        return Err(format!("{url} isn't a YouTube playlist URL!"));
+    }
+
+    let mut playlist_id = playlist_id.unwrap();
+
+    // removes & from urls like this: https://youtube.com/playlist?list=id&si=blahblahblah
+    if let Some(ampersand) = playlist_id.find("&") {
+        playlist_id = playlist_id.split_at(ampersand).0;
+    }
+
+    if playlist_id.is_empty() {
+        return Err(format!("Couldn't find a playlist id in {url}!"));
+    }
+
+    Ok(String::from(playlist_id))
+}
+
+#[allow(non_snake_case)] // needed for youtube api
+#[derive(Deserialize)]
+struct PlaylistItemListResponse {
+    items: Vec<PlaylistItemResponse>,
+    nextPageToken: Option<String>,
+}
+
+#[allow(non_snake_case)] // needed for youtube api
+#[derive(Deserialize)]
+struct PlaylistItemResponse {
+    contentDetails: PlaylistItemContentDetails,
+}
+
+#[allow(non_snake_case)] // needed for youtube api
+#[derive(Deserialize)]
+struct PlaylistItemContentDetails {
+    videoId: String,
+}
+
+pub struct PlaylistRequest<'a> {
+    pub url: &'a String,
+    pub api: YouTubeApi,
+    pub filters: &'a VideoFilters,
+}
+
+/// Lists every video in a playlist one page at a time, the same way as `request_channel`.
+pub fn request_playlist(
+    params: PlaylistRequest<'_>,
+) -> impl Stream<Item = Result<Vec<String>, String>> + '_ {
+    stream! {
+        let client = params.api.client.clone();
+        let playlist_id = get_playlist_id_from_url(params.url);
+        if playlist_id.is_err() {
+            yield Err(playlist_id.err().unwrap());
+            return;
+        }
+
+        let playlist_id = playlist_id.unwrap();
+        request(format!(
+            "Requesting all videos from playlist ID {}",
+            &playlist_id
+        ));
+
+        let mut next_page: Option<String> = None;
+        loop {
+            let page_result = retry_page(|| {
+                request_filtered_page(
+                    request_playlist_page(PlaylistPageParameters {
+                        playlist_id: &playlist_id,
+                        api: &params.api,
+                        next_page: &next_page,
+                        client: &client,
+                    }),
+                    PageFilterParameters {
+                        api: &params.api,
+                        filters: params.filters,
+                        client: &client,
+                    },
+                )
+            })
+            .await;
+            if page_result.is_err() {
+                let error = page_result.err().unwrap();
+                yield Err(format!(
+                    "There was an error getting all videos in the specified playlist! Error: {error}"
+                ));
+                return;
+            }
+
+            let page = page_result.unwrap();
+            yield Ok(page.ids);
+
+            next_page = page.next_page;
+            if next_page.is_none() {
+                break;
+            }
+        }
+    }
+}
+
+struct VideoPage {
+    // video ids, or the urls of the videos to archive once the page has been filtered
+    ids: Vec<String>,
+    next_page: Option<String>,
+}
+
+const PAGE_ATTEMPTS: u32 = 4;
+
+/// Requests a page and applies the filters to it, so that checking the videos is retried along with the page.
+/// The ids of the page are replaced with the urls of the videos to archive.
+async fn request_filtered_page<R>(
+    request_page: R,
+    params: PageFilterParameters<'_>,
+) -> Result<VideoPage, String>
+where
+    R: Future<Output = Result<VideoPage, String>>,
+{
+    let page_result = request_page.await;
+    if page_result.is_err() {
+        return Err(page_result.err().unwrap());
+    }
+
+    let page = page_result.unwrap();
+    let videos = filter_page(page.ids, params).await;
+    if videos.is_err() {
+        return Err(videos.err().unwrap());
+    }
+
+    Ok(VideoPage {
+        ids: videos.unwrap(),
+        next_page: page.next_page,
     })
+}
+
+/// Tries to request a page a few times, waiting longer after each failure,
+/// so that one bad response doesn't throw away every page requested before it.
+async fn retry_page<F, R>(mut request_page: F) -> Result<VideoPage, String>
+where
+    F: FnMut() -> R,
+    R: Future<Output = Result<VideoPage, String>>,
+{
+    let mut attempt = 1;
+    loop {
+        let page_result = request_page().await;
+        if page_result.is_ok() || attempt == PAGE_ATTEMPTS {
+            return page_result;
+        }
+
+        let error = page_result.err().unwrap();
+        let delay = 2_u64.pow(attempt - 1);
+        retry(format!(
+            "Failed to get a page of videos on attempt {attempt} of {PAGE_ATTEMPTS}, trying again in {delay} seconds. Error: {error}"
+        ));
+        sleep(Duration::from_secs(delay)).await;
+        attempt += 1;
+    }
+}
+
+struct PlaylistPageParameters<'a> {
+    playlist_id: &'a String,
+    api: &'a YouTubeApi,
+    next_page: &'a Option<String>,
+    client: &'a HttpClient,
+}
+
+async fn request_playlist_page(params: PlaylistPageParameters<'_>) -> Result<VideoPage, String> {
+    let mut page_url = params.api.get_endpoint(format!(
+        "playlistItems?part=contentDetails&maxResults=50&playlistId={}",
+        params.playlist_id
+    ));
+    if let Some(page) = params.next_page {
+        page_url += format!("&pageToken={page}").as_str();
+    }
+
+    let result = get_request(
+        GetRequest {
+            url: page_url,
+            accept: Some(String::from("application/json")),
+        },
+        params.client,
+    )
     .await;
+    if result.is_err() {
+        let error = result.err().unwrap();
+        return Err(format!("There was an error requesting the playlist videos from the playlist id {}! Error: {error}", params.playlist_id));
+    }
 
-    if videos_request.is_err() {
-        let error = videos_request.err().unwrap();
+    let list_result = result.unwrap().json::<PlaylistItemListResponse>().await;
+    if list_result.is_err() {
+        let error = list_result.err().unwrap();
         return Err(format!(
-            "There was an error getting all videos on the specified channel! Error: {error}"
+            "There was an error parsing the playlist items! Error: {error}"
         ));
     }
 
-    Ok(videos_request.unwrap())
-}
+    let list_response = list_result.unwrap();
+    let mut ids: Vec<String> = Vec::new();
+    for item in list_response.items {
+        ids.push(item.contentDetails.videoId);
+    }
 
-pub fn get_channel_handle_from_url(url: &String) -> String {
-    assert!(
-        url.find("@").is_some(),
-        "Make sure to provide a valid YouTube Channel URL!"
-    );
-    return String::from(url.split_at(url.find("@").unwrap() + 1).1);
+    Ok(VideoPage {
+        ids,
+        next_page: list_response.nextPageToken,
+    })
 }
 
 #[allow(non_snake_case)] // needed for youtube api
@@ -408,43 +920,43 @@ struct IDResponse {
     // channelId: Option<String>,
 }
 
-struct VideosRequestParameters {
-    channel_id: String,
-    api: String,
-    next_page: Option<String>,
-    previous_videos: Option<Vec<String>>,
-    include_streams_and_premieres: bool,
-    client: Client,
+struct SearchPageParameters<'a> {
+    channel_id: &'a String,
+    api: &'a YouTubeApi,
+    next_page: &'a Option<String>,
+    filters: &'a VideoFilters,
+    client: &'a HttpClient,
 }
 
-#[async_recursion]
-async fn request_videos(params: VideosRequestParameters) -> Result<Vec<String>, String> {
-    let mut videos: Vec<String>;
-    if params.previous_videos.is_some() {
-        videos = params.previous_videos.unwrap();
-    } else {
-        videos = Vec::new();
-    }
-
+async fn request_search_page(params: SearchPageParameters<'_>) -> Result<VideoPage, String> {
     request(format!(
-        "Requesting an initial search for all videos from {}!",
+        "Requesting a page of the search for all videos from {}!",
+        params.channel_id
+    ));
+    let mut page_url = params.api.get_endpoint(format!(
+        "search?part=snippet,id&order=date&type=video&maxResults=50&channelId={}",
         params.channel_id
     ));
-    let mut initial_url = format!(
-        "{}/noKey/search?part=snippet,id&order=date&type=video&maxResults=50&channelId={}",
-        params.api, params.channel_id
-    );
 
-    if params.next_page.is_some() {
-        initial_url += format!("&pageToken={}", params.next_page.unwrap()).as_str();
+    if let Some(next_page) = params.next_page {
+        page_url += format!("&pageToken={next_page}").as_str();
+    }
+
+    // the search api can skip videos outside of the date range by itself, which saves requesting pages of them
+    if let Some(after) = params.filters.after {
+        page_url += format!("&publishedAfter={after}T00:00:00Z").as_str();
+    }
+
+    if let Some(before) = params.filters.before.and_then(|before| before.succ_opt()) {
+        page_url += format!("&publishedBefore={before}T00:00:00Z").as_str();
     }
 
     let result = get_request(
         GetRequest {
-            url: initial_url,
+            url: page_url,
             accept: Some(String::from("application/json")),
         },
-        &params.client,
+        params.client,
     )
     .await;
     if result.is_err() {
@@ -465,97 +977,575 @@ async fn request_videos(params: VideosRequestParameters) -> Result<Vec<String>,
     ));
 
     let search_list = search_parse_result.unwrap();
+    let mut ids: Vec<String> = Vec::new();
     for search_result in search_list.items {
         if search_result.id.kind != "youtube#video" {
             // shouldn't be possible but just in case ig
             continue;
         }
 
-        let id = &search_result.id.videoId.unwrap();
-        if params.include_streams_and_premieres {
+        if let Some(video_id) = search_result.id.videoId {
+            ids.push(video_id);
+        }
+    }
+
+    Ok(VideoPage {
+        ids,
+        next_page: search_list.nextPageToken,
+    })
+}
+
+pub(crate) struct PageFilterParameters<'a> {
+    pub api: &'a YouTubeApi,
+    pub filters: &'a VideoFilters,
+    pub client: &'a HttpClient,
+}
+
+/// Applies the video filters to a page of video ids, returning the urls of the videos to archive.
+pub(crate) async fn filter_page(
+    ids: Vec<String>,
+    params: PageFilterParameters<'_>,
+) -> Result<Vec<String>, String> {
+    let mut videos: Vec<String> = Vec::new();
+    if !params.filters.is_active() {
+        for id in ids {
             videos.push(format!("https://youtu.be/{id}"));
-            continue;
         }
 
-        let is_stream_result = is_video_a_stream(id, &params.api, &params.client).await;
-        if is_stream_result.is_err() {
-            let error = is_stream_result.err().unwrap();
+        return Ok(videos);
+    }
+
+    let details_result = request_video_details(&ids, params.api, params.client).await;
+    if details_result.is_err() {
+        let error = details_result.err().unwrap();
+        return Err(format!(
+            "Failed to check videos against the filters! Error: {error}"
+        ));
+    }
+
+    let details = details_result.unwrap();
+    for id in ids {
+        let video_details = details.iter().find(|video| video.id == id);
+        if video_details.is_none() {
             failure(format!(
-                "Failed to check if video was a livestream! Error: {error}"
+                "Couldn't find details for video {id}, so it was skipped."
             ));
             continue;
         }
 
-        if !is_stream_result.unwrap() {
-            videos.push(format!("https://youtu.be/{id}"));
+        let video_details = video_details.unwrap();
+        let mut check_result = params.filters.check(video_details);
+        if check_result.is_ok() && params.filters.checks_shorts() {
+            let short_result = request_is_short(video_details, params.client).await;
+            if short_result.is_err() {
+                let error = short_result.err().unwrap();
+                return Err(format!(
+                    "Failed to check whether video {id} is a short! Error: {error}"
+                ));
+            }
+
+            check_result = params.filters.check_short(short_result.unwrap());
         }
-    }
 
-    if search_list.nextPageToken.is_some() {
-        return request_videos(VideosRequestParameters {
-            channel_id: params.channel_id,
-            api: params.api,
-            next_page: Some(search_list.nextPageToken.unwrap().clone()),
-            previous_videos: Some(videos),
-            include_streams_and_premieres: params.include_streams_and_premieres,
-            client: params.client,
-        })
-        .await;
+        if check_result.is_err() {
+            let reason = check_result.err().unwrap();
+            request(format!("Skipping video {id} because {reason}."));
+            continue;
+        }
+
+        videos.push(format!("https://youtu.be/{id}"));
     }
 
     Ok(videos)
 }
 
+// shorts can't be longer than three minutes
+const MAX_SHORT_DURATION: u64 = 180;
+
+/// Works out whether a video is a short, which the api doesn't say.
+/// Youtube serves shorts at /shorts/<id> and redirects anything else there to the regular watch page.
+async fn request_is_short(
+    details: &VideoDetailsResponse,
+    client: &HttpClient,
+) -> Result<bool, String> {
+    // durations that couldn't be parsed come out as 0, and those videos are left as regular ones
+    let duration = parse_duration(&details.contentDetails.duration);
+    if duration == 0 || duration > MAX_SHORT_DURATION {
+        return Ok(false);
+    }
+
+    let result = head_request(
+        &format!("https://www.youtube.com/shorts/{}", details.id),
+        client,
+    )
+    .await;
+    if result.is_err() {
+        return Err(result.err().unwrap());
+    }
+
+    let status = result.unwrap().status();
+    if status.is_success() {
+        return Ok(true);
+    }
+
+    if status.is_redirection() {
+        return Ok(false);
+    }
+
+    Err(format!("The shorts page responded with status {status}!"))
+}
+
 #[allow(non_snake_case)] // needed for youtube api
-#[derive(Deserialize)]
-struct VideoListResponse {
-    items: Vec<VideoResult>,
+#[derive(Debug, Deserialize)]
+struct VideoDetailsListResponse {
+    items: Vec<VideoDetailsResponse>,
 }
 
 #[allow(non_snake_case)] // needed for youtube api
-#[derive(Deserialize)]
-struct VideoResult {
-    liveStreamingDetails: Option<LiveStreamingDetails>,
+#[derive(Debug, Deserialize)]
+pub struct VideoDetailsResponse {
+    pub id: String,
+    pub snippet: SnippetResponse,
+    pub contentDetails: ContentDetailsResponse,
+    pub statistics: Option<StatisticsResponse>,
+    pub liveStreamingDetails: Option<LiveStreamingDetailsResponse>,
 }
 
 #[allow(non_snake_case)] // needed for youtube api
-#[derive(Deserialize)]
-struct LiveStreamingDetails {
-    // actualStartTime: String,
-    // actualEndTime: String,
+#[derive(Debug, Deserialize)]
+pub struct LiveStreamingDetailsResponse {
+    pub scheduledStartTime: Option<String>,
+    pub actualStartTime: Option<String>,
+    pub actualEndTime: Option<String>,
 }
 
-async fn is_video_a_stream(id: &String, api: &String, client: &Client) -> Result<bool, String> {
-    let result = get_request(
-        GetRequest {
-            url: format!("{api}/noKey/videos?part=liveStreamingDetails&id={id}"),
-            accept: Some(String::from("application/json")),
-        },
-        client,
-    )
-    .await;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BroadcastKind {
+    Video,
+    Stream,
+    Premiere,
+    Upcoming,
+}
 
-    if result.is_err() {
-        let error = result.err().unwrap();
-        return Err(format!(
-            "There was an error requesting live stream details from video {}! Error: {error}",
-            id
-        ));
+// premieres start on their own right when they were scheduled to, while streams start whenever someone goes live
+const MAX_PREMIERE_START_DELAY: i64 = 5;
+
+impl VideoDetailsResponse {
+    /// Works out what sort of broadcast a video was, if any.
+    /// The api doesn't mark premieres, so this is a guess: a broadcast that started within a few seconds
+    /// of when it was scheduled and ran at least as long as its video is taken to be a premiere.
+    pub fn get_broadcast_kind(&self) -> BroadcastKind {
+        match self.snippet.liveBroadcastContent.as_deref() {
+            Some("upcoming") => return BroadcastKind::Upcoming,
+            Some("live") => return BroadcastKind::Stream,
+            _ => {}
+        }
+
+        let details = match &self.liveStreamingDetails {
+            Some(details) => details,
+            None => return BroadcastKind::Video,
+        };
+
+        // scheduled broadcasts that never started have no actual start time
+        let start = details
+            .actualStartTime
+            .as_ref()
+            .and_then(|start| DateTime::parse_from_rfc3339(start).ok());
+        let end = details
+            .actualEndTime
+            .as_ref()
+            .and_then(|end| DateTime::parse_from_rfc3339(end).ok());
+        let scheduled = details
+            .scheduledStartTime
+            .as_ref()
+            .and_then(|scheduled| DateTime::parse_from_rfc3339(scheduled).ok());
+        match (start, end, scheduled) {
+            (None, _, _) => BroadcastKind::Upcoming,
+            (Some(_), None, _) | (Some(_), Some(_), None) => BroadcastKind::Stream,
+            (Some(start), Some(end), Some(scheduled)) => {
+                let start_delay = (start - scheduled).num_seconds().abs();
+                // premieres play the whole video, while youtube may only keep part of a long stream
+                let broadcast_length = (end - start).num_seconds();
+                let video_length = parse_duration(&self.contentDetails.duration) as i64;
+                if start_delay <= MAX_PREMIERE_START_DELAY && broadcast_length >= video_length {
+                    BroadcastKind::Premiere
+                } else {
+                    BroadcastKind::Stream
+                }
+            }
+        }
     }
+}
 
-    let video_parse_result = result.unwrap().json::<VideoListResponse>().await;
-    if video_parse_result.is_err() {
-        let error = video_parse_result.err().unwrap();
-        return Err(format!(
-            "There was an error parsing the video list! Error: {error}"
+#[allow(non_snake_case)] // needed for youtube api
+#[derive(Debug, Deserialize)]
+pub struct ContentDetailsResponse {
+    pub duration: String,
+}
+
+#[allow(non_snake_case)] // needed for youtube api
+#[derive(Debug, Deserialize)]
+pub struct StatisticsResponse {
+    pub viewCount: Option<String>,
+    pub likeCount: Option<String>,
+    pub commentCount: Option<String>,
+}
+
+/// Requests the details of many videos at once, 50 at a time as that's the most the api allows.
+async fn request_video_details(
+    ids: &[String],
+    api: &YouTubeApi,
+    client: &HttpClient,
+) -> Result<Vec<VideoDetailsResponse>, String> {
+    let mut details: Vec<VideoDetailsResponse> = Vec::new();
+    for chunk in ids.chunks(50) {
+        let result = get_request(
+            GetRequest {
+                url: api.get_endpoint(format!(
+                    "videos?part=snippet,contentDetails,statistics,liveStreamingDetails&id={}",
+                    chunk.join(",")
+                )),
+                accept: Some(String::from("application/json")),
+            },
+            client,
+        )
+        .await;
+        if result.is_err() {
+            let error = result.err().unwrap();
+            return Err(format!(
+                "There was an error requesting video details! Error: {error}"
+            ));
+        }
+
+        let list_result = result.unwrap().json::<VideoDetailsListResponse>().await;
+        if list_result.is_err() {
+            let error = list_result.err().unwrap();
+            return Err(format!(
+                "There was an error parsing the video details! Error: {error}"
+            ));
+        }
+
+        details.extend(list_result.unwrap().items);
+    }
+
+    Ok(details)
+}
+
+/// Parses durations from the api like `PT1H2M3S` into seconds, returning 0 for anything unexpected.
+pub fn parse_duration(duration: &str) -> u64 {
+    let mut seconds = 0;
+    let mut number = String::new();
+    for character in duration.chars() {
+        match character {
+            '0'..='9' => number.push(character),
+            'W' | 'D' | 'H' | 'M' | 'S' => {
+                let value: u64 = number.parse().unwrap_or(0);
+                number.clear();
+                seconds += value
+                    * match character {
+                        'W' => 604800,
+                        'D' => 86400,
+                        'H' => 3600,
+                        'M' => 60,
+                        _ => 1,
+                    };
+            }
+            _ => number.clear(),
+        }
+    }
+
+    seconds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::replay_client;
+    use futures::StreamExt;
+
+    fn test_api() -> YouTubeApi {
+        YouTubeApi {
+            url: String::from("https://api.example.com"),
+            key: None,
+            client: replay_client("youtube"),
+        }
+    }
+
+    #[tokio::test]
+    async fn follows_every_page_of_a_channel() {
+        let url = String::from("https://www.youtube.com/@archivetest");
+        let filters = VideoFilters::default();
+        let pages: Vec<_> = request_channel(ChannelRequest {
+            url: &url,
+            api: test_api(),
+            filters: &filters,
+        })
+        .collect()
+        .await;
+
+        let pages: Vec<Vec<String>> = pages.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            pages,
+            vec![
+                vec![
+                    String::from("https://youtu.be/video00001"),
+                    String::from("https://youtu.be/video00002"),
+                ],
+                vec![String::from("https://youtu.be/video00003")],
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ends_the_channel_after_a_page_keeps_failing() {
+        let url = String::from("https://www.youtube.com/@brokentest");
+        let filters = VideoFilters::default();
+        let pages: Vec<_> = request_channel(ChannelRequest {
+            url: &url,
+            api: test_api(),
+            filters: &filters,
+        })
+        .collect()
+        .await;
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(
+            pages[0].as_ref().unwrap(),
+            &vec![String::from("https://youtu.be/video00004")]
+        );
+        let error = pages[1].as_ref().err().unwrap();
+        assert!(
+            error.contains("error parsing the channel search results"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn parses_video_metadata() {
+        let availability = request_video_metadata("https://youtu.be/video00001", &test_api())
+            .await
+            .unwrap();
+        let item = match availability {
+            VideoAvailability::Available(item) => item,
+            VideoAvailability::Unavailable(_) => panic!("video00001 should be available"),
+        };
+
+        assert_eq!(item.id, "video00001");
+        assert_eq!(item.snippet.title, "Archiving Test");
+        assert_eq!(item.snippet.channelId.as_deref(), Some("UCarchivetest"));
+        assert_eq!(
+            item.snippet
+                .thumbnails
+                .get("maxres")
+                .as_ref()
+                .unwrap()
+                .width,
+            Some(1280)
+        );
+        assert!(item.snippet.thumbnails.get("high").is_none());
+        let duration = &item.contentDetails.as_ref().unwrap().duration;
+        assert_eq!(parse_duration(duration), 3723);
+        assert_eq!(
+            item.statistics.as_ref().unwrap().viewCount.as_deref(),
+            Some("1234")
+        );
+    }
+
+    #[tokio::test]
+    async fn works_out_why_videos_are_unavailable() {
+        let api = test_api();
+        for (url, expected) in [
+            ("https://youtu.be/deleted0001", UnavailableReason::Deleted),
+            ("https://youtu.be/private0001", UnavailableReason::Private),
+        ] {
+            match request_video_metadata(url, &api).await.unwrap() {
+                VideoAvailability::Unavailable(reason) => assert_eq!(reason, expected),
+                VideoAvailability::Available(_) => panic!("{url} should be unavailable"),
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fails_the_page_when_its_videos_cant_be_filtered() {
+        let url = String::from("https://www.youtube.com/@archivetest");
+        // there is no fixture for the video details, so checking the filters always fails
+        let filters = VideoFilters {
+            min_duration: Some(60),
+            ..VideoFilters::default()
+        };
+        let pages: Vec<_> = request_channel(ChannelRequest {
+            url: &url,
+            api: test_api(),
+            filters: &filters,
+        })
+        .collect()
+        .await;
+
+        assert_eq!(pages.len(), 1);
+        let error = pages[0].as_ref().err().unwrap();
+        assert!(
+            error.contains("Failed to check videos against the filters"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn filters_out_shorts() {
+        let api = test_api();
+        let filters = VideoFilters {
+            shorts: ShortsFilter::Exclude,
+            ..VideoFilters::default()
+        };
+        let videos = filter_page(
+            vec![
+                String::from("short00001"),
+                String::from("clip0000001"),
+                String::from("untimed0001"),
+            ],
+            PageFilterParameters {
+                api: &api,
+                filters: &filters,
+                client: &api.client,
+            },
+        )
+        .await
+        .unwrap();
+
+        // short videos that redirect away from /shorts aren't shorts, and neither are videos without a duration
+        assert_eq!(
+            videos,
+            vec![
+                String::from("https://youtu.be/clip0000001"),
+                String::from("https://youtu.be/untimed0001"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_channels_without_an_id() {
+        let url = String::from("https://www.youtube.com/@unknowntest");
+        let filters = VideoFilters::default();
+        let pages: Vec<_> = request_channel(ChannelRequest {
+            url: &url,
+            api: test_api(),
+            filters: &filters,
+        })
+        .collect()
+        .await;
+
+        assert_eq!(pages.len(), 1);
+        let error = pages[0].as_ref().err().unwrap();
+        assert!(
+            error.contains("@unknowntest has no associated channel"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn doesnt_save_error_pages_as_thumbnails() {
+        let root = std::env::temp_dir().join(format!(
+            "youtube_archiver_missing_thumbnail_{}",
+            std::process::id()
         ));
+        let paths = ArchivePaths {
+            root: root.to_string_lossy().to_string(),
+            dir: String::new(),
+            name: String::from(ARTIFACT_MARKER),
+        };
+        let filename = paths.get_file("thumb_default", "jpg");
+        let result = download_thumbnail(ThumbnailParameters {
+            url: &String::from("https://i.ytimg.com/vi/missing0001/default.jpg"),
+            filename: filename.clone(),
+            history_filename: paths.get_history_file("thumb_default", "jpg", None),
+            paths: &paths,
+            client: &replay_client("youtube"),
+        })
+        .await;
+
+        assert!(result.err().unwrap().contains("404"));
+        assert!(!std::path::Path::new(&filename).exists());
+    }
+
+    #[test]
+    fn parses_ids_from_urls() {
+        for url in [
+            "https://youtu.be/dQw4w9WgXcQ?si=share",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PLlist",
+            "https://youtu.be/dQw4w9WgXcQ?t=11",
+        ] {
+            assert_eq!(get_id_from_url(url).unwrap(), "dQw4w9WgXcQ");
+        }
+        assert_eq!(
+            get_channel_handle_from_url("https://www.youtube.com/@handle/videos").unwrap(),
+            "handle"
+        );
+        assert_eq!(
+            get_playlist_id_from_url("https://youtube.com/playlist?list=PLlist&si=share").unwrap(),
+            "PLlist"
+        );
     }
 
-    let video_list = video_parse_result.unwrap();
-    assert!(
-        video_list.items.len() > 0,
-        "Provide a valid YouTube video ID!"
-    );
+    #[test]
+    fn rejects_urls_without_an_id() {
+        for url in [
+            "https://youtube.com",
+            "https://youtube.com/",
+            "https://example.com/video",
+            "https://youtube.com/@",
+            "https://youtube.com/playlist?list=",
+        ] {
+            assert!(check_url(url).is_err(), "{url}");
+        }
+        assert!(check_url("https://youtu.be/dQw4w9WgXcQ").is_ok());
+    }
 
-    Ok(video_list.items[0].liveStreamingDetails.is_some())
+    #[test]
+    fn tells_premieres_from_streams() {
+        let details = |scheduled: &str, start: &str, end: &str, duration: &str| {
+            serde_json::from_value::<VideoDetailsResponse>(serde_json::json!({
+                "id": "video00001",
+                "snippet": {
+                    "publishedAt": "2024-05-01T12:00:00Z",
+                    "title": "Archiving Test",
+                    "description": "",
+                    "thumbnails": {},
+                    "channelTitle": "Archive Test",
+                    "liveBroadcastContent": "none"
+                },
+                "contentDetails": { "duration": duration },
+                "liveStreamingDetails": {
+                    "scheduledStartTime": scheduled,
+                    "actualStartTime": start,
+                    "actualEndTime": end
+                }
+            }))
+            .unwrap()
+        };
+
+        // a premiere with a countdown far longer than a few minutes
+        let premiere = details(
+            "2024-05-01T12:00:00Z",
+            "2024-05-01T12:00:01Z",
+            "2024-05-01T12:40:00Z",
+            "PT10M",
+        );
+        assert_eq!(premiere.get_broadcast_kind(), BroadcastKind::Premiere);
+
+        // a short stream that went live a little after it was scheduled
+        let stream = details(
+            "2024-05-01T12:00:00Z",
+            "2024-05-01T12:02:30Z",
+            "2024-05-01T12:06:00Z",
+            "PT3M30S",
+        );
+        assert_eq!(stream.get_broadcast_kind(), BroadcastKind::Stream);
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("PT1H2M3S"), 3723);
+        assert_eq!(parse_duration("P1DT1S"), 86401);
+        assert_eq!(parse_duration("PT45S"), 45);
+        assert_eq!(parse_duration("P0D"), 0);
+    }
 }
@@ -0,0 +1,227 @@
+/*
+    File used to write metadata in the `.info.json` format of yt-dlp,
+    which media servers and other archiving tools already know how to read.
+*/
+use crate::archive::*;
+use crate::file::*;
+use crate::log::*;
+use crate::youtube::*;
+use chrono::DateTime;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+pub const INFO_JSON_ARTIFACT: &str = "info_json";
+pub const INFO_JSON_EXTENSION: &str = "info.json";
+
+#[derive(Debug, Clone, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MetadataFormat {
+    Archiver,
+    InfoJson,
+    Both,
+}
+
+impl MetadataFormat {
+    pub fn writes_archiver(&self) -> bool {
+        *self != MetadataFormat::InfoJson
+    }
+
+    pub fn writes_info_json(&self) -> bool {
+        *self != MetadataFormat::Archiver
+    }
+}
+
+#[derive(Serialize)]
+struct InfoJson {
+    id: String,
+    title: String,
+    fulltitle: String,
+    display_id: String,
+    description: String,
+    uploader: String,
+    // yt-dlp also has uploader_id and uploader_url for the channel's @handle,
+    // which are left out since the api doesn't give handles along with videos
+    channel: String,
+    channel_id: Option<String>,
+    channel_url: Option<String>,
+    // YYYYMMDD
+    upload_date: Option<String>,
+    timestamp: Option<i64>,
+    duration: Option<u64>,
+    view_count: Option<u64>,
+    like_count: Option<u64>,
+    comment_count: Option<u64>,
+    tags: Vec<String>,
+    thumbnails: Vec<InfoJsonThumbnail>,
+    thumbnail: Option<String>,
+    chapters: Option<Vec<InfoJsonChapter>>,
+    live_status: String,
+    webpage_url: String,
+    original_url: String,
+    extractor: String,
+    extractor_key: String,
+    _type: String,
+}
+
+#[derive(Serialize)]
+struct InfoJsonThumbnail {
+    id: String,
+    url: String,
+    width: Option<u64>,
+    height: Option<u64>,
+    // higher is better, the same way yt-dlp ranks thumbnails
+    preference: i64,
+}
+
+#[derive(Serialize)]
+struct InfoJsonChapter {
+    start_time: u64,
+    end_time: u64,
+    title: String,
+}
+
+fn parse_count(count: &Option<String>) -> Option<u64> {
+    count.as_ref().and_then(|count| count.parse::<u64>().ok())
+}
+
+fn create_info_json(item: &ItemResponse) -> InfoJson {
+    let snippet = &item.snippet;
+    let published = DateTime::parse_from_rfc3339(&snippet.publishedAt).ok();
+    let duration = item
+        .contentDetails
+        .as_ref()
+        .map(|details| parse_duration(&details.duration));
+    let statistics = item.statistics.as_ref();
+
+    let mut thumbnails: Vec<InfoJsonThumbnail> = Vec::new();
+    for (preference, size) in THUMBNAIL_SIZES.iter().enumerate() {
+        if let Some(thumbnail) = snippet.thumbnails.get(size) {
+            thumbnails.push(InfoJsonThumbnail {
+                id: String::from(*size),
+                url: thumbnail.url.clone(),
+                width: thumbnail.width,
+                height: thumbnail.height,
+                preference: preference as i64,
+            });
+        }
+    }
+
+    let chapters = duration.and_then(|duration| parse_chapters(&snippet.description, duration));
+    let live_status = match snippet.liveBroadcastContent.as_deref() {
+        Some("live") => "is_live",
+        Some("upcoming") => "is_upcoming",
+        _ if item.liveStreamingDetails.is_some() => "was_live",
+        _ => "not_live",
+    };
+    let webpage_url = format!("https://www.youtube.com/watch?v={}", item.id);
+    let channel_url = snippet
+        .channelId
+        .as_ref()
+        .map(|id| format!("https://www.youtube.com/channel/{id}"));
+
+    InfoJson {
+        id: item.id.clone(),
+        title: snippet.title.clone(),
+        fulltitle: snippet.title.clone(),
+        display_id: item.id.clone(),
+        description: snippet.description.clone(),
+        uploader: snippet.channelTitle.clone(),
+        channel: snippet.channelTitle.clone(),
+        channel_id: snippet.channelId.clone(),
+        channel_url,
+        upload_date: published.map(|date| date.format("%Y%m%d").to_string()),
+        timestamp: published.map(|date| date.timestamp()),
+        duration,
+        view_count: statistics.and_then(|statistics| parse_count(&statistics.viewCount)),
+        like_count: statistics.and_then(|statistics| parse_count(&statistics.likeCount)),
+        comment_count: statistics.and_then(|statistics| parse_count(&statistics.commentCount)),
+        tags: snippet.tags.clone().unwrap_or_default(),
+        thumbnail: thumbnails.last().map(|thumbnail| thumbnail.url.clone()),
+        thumbnails,
+        chapters,
+        live_status: String::from(live_status),
+        webpage_url: webpage_url.clone(),
+        original_url: webpage_url,
+        extractor: String::from("youtube"),
+        extractor_key: String::from("Youtube"),
+        _type: String::from("video"),
+    }
+}
+
+/// Parses a timestamp like `1:02:03` or `2:03` into seconds.
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let parts: Vec<&str> = timestamp.split(":").collect();
+    if parts.len() < 2 || parts.len() > 3 {
+        return None;
+    }
+
+    let mut seconds = 0;
+    for (index, part) in parts.iter().enumerate() {
+        // every part but the first needs two digits, like youtube expects
+        if part.is_empty() || (index > 0 && part.len() != 2) {
+            return None;
+        }
+
+        let value = match part.parse::<u64>() {
+            Ok(value) if index == 0 || value < 60 => value,
+            _ => return None,
+        };
+
+        seconds = seconds * 60 + value;
+    }
+
+    Some(seconds)
+}
+
+/// Finds chapters in a description the same way youtube does: lines starting with (or only holding) a timestamp,
+/// where the first one is at 0:00, there are at least three of them and they only go forwards.
+fn parse_chapters(description: &str, duration: u64) -> Option<Vec<InfoJsonChapter>> {
+    let mut starts: Vec<(u64, String)> = Vec::new();
+    for line in description.lines() {
+        let line = line.trim();
+        let (timestamp, title) = match line.split_once(char::is_whitespace) {
+            Some((timestamp, title)) => (timestamp, title),
+            None => (line, ""),
+        };
+        let start = match parse_timestamp(timestamp) {
+            Some(start) => start,
+            None => continue,
+        };
+
+        let title = title.trim_start_matches(['-', '–', ':', '|', ' ']).trim();
+        starts.push((start, String::from(title)));
+    }
+
+    if starts.len() < 3
+        || starts[0].0 != 0
+        || starts.windows(2).any(|pair| pair[0].0 >= pair[1].0)
+        || starts.last().unwrap().0 >= duration
+    {
+        return None;
+    }
+
+    let mut chapters: Vec<InfoJsonChapter> = Vec::new();
+    for (index, (start, title)) in starts.iter().enumerate() {
+        let end = starts.get(index + 1).map(|next| next.0).unwrap_or(duration);
+        chapters.push(InfoJsonChapter {
+            start_time: *start,
+            end_time: end,
+            title: title.clone(),
+        });
+    }
+
+    Some(chapters)
+}
+
+/// Writes the metadata of a video as a yt-dlp `.info.json` file, named after the video so that tools can pair them up.
+pub fn write_info_json(item: &ItemResponse, paths: &ArchivePaths) -> Result<(), String> {
+    let filename = paths.get_video_file("source_h264", INFO_JSON_EXTENSION);
+    let contents = serde_json::to_string_pretty(&create_info_json(item)).unwrap();
+    let write_result = write_atomic(&filename, contents.as_bytes());
+    if write_result.is_err() {
+        return Err(write_result.err().unwrap());
+    }
+
+    success(format!("Wrote to requested file {filename} successfully!"));
+    Ok(())
+}